@@ -3,14 +3,20 @@ use std::io::BufRead;
 use std::path::Path;
 
 use serde::{Deserialize, Serialize};
-use serde_json;
 
+use sheltie::analyzer::{AnalyzerConfig, TokenFilterConfig, TokenizerConfig};
 use sheltie::index::IndexWriter;
+use sheltie::schema::{Document, FieldEntry, Schema};
 
 #[derive(Debug, Serialize, Deserialize)]
 struct Record {
     id: String,
-    text: String,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    body: String,
+    #[serde(default)]
+    tags: Vec<String>,
 }
 
 fn parse_json(data: &str) -> serde_json::Result<Record> {
@@ -31,7 +37,22 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     };
 
     // Open the IndexWriter.
-    let mut writer = IndexWriter::new(Path::new(index_dir_path));
+    let text = AnalyzerConfig::new(
+        TokenizerConfig::UnicodeWord,
+        vec![TokenFilterConfig::Lowercase],
+    );
+    let schema = Schema::new()
+        .with_field(FieldEntry::new("id").with_indexed(false).with_stored(true))
+        .with_field(
+            FieldEntry::new("title")
+                .with_analyzer(text.clone())
+                .with_stored(true),
+        )
+        .with_field(FieldEntry::new("body").with_analyzer(text))
+        .with_field(FieldEntry::new("tags").with_stored(true))
+        .with_unique_key("id");
+    let mut writer = IndexWriter::with_schema(Path::new(index_dir_path), schema)
+        .map_err(|err| format!("Failed to create the index writer: {}", err))?;
 
     // Read documents from stding and index it.
     let stdin = std::io::stdin();
@@ -41,11 +62,20 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             continue;
         }
         let record = parse_json(&line).map_err(|err| format!("Failed to parse JSON: {}", err))?;
-        writer.add(&record.text);
+        let mut document = Document::new()
+            .with_value("id", &record.id)
+            .with_value("title", &record.title)
+            .with_value("body", &record.body);
+        for tag in &record.tags {
+            document.add_value("tags", tag);
+        }
+        writer
+            .add(&document)
+            .map_err(|err| format!("Failed to add {:?}: {}", record.id, err))?;
     }
 
     // Write out the index.
-    writer.export_index()?;
+    writer.commit()?;
 
     Ok(())
 }
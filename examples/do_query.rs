@@ -1,11 +1,10 @@
 use std::env;
-use std::fs::File;
 use std::io::prelude::*;
+use std::path::Path;
 
 use serde::{Deserialize, Serialize};
 
-use sheltie::index::Index;
-use sheltie::searcher::Searcher;
+use sheltie::reader::IndexReader;
 
 #[derive(Debug, Serialize, Deserialize)]
 struct Record {
@@ -21,19 +20,20 @@ fn parse_json(data: &str) -> serde_json::Result<Record> {
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args: Vec<String> = env::args().collect();
     let index_path = match args.len() {
-        1 => panic!("Usage: cargo run --example do_query examples/data/segment.doc < examples/data/queries.txt"),
+        1 => {
+            panic!("Usage: cargo run --example do_query examples/data < examples/data/queries.txt")
+        }
         2 => &args[1],
-        _ => panic!("Usage: cargo run --example do_query examples/data/segment.doc < examples/data/queries.txt"),
+        _ => {
+            panic!("Usage: cargo run --example do_query examples/data < examples/data/queries.txt")
+        }
     };
 
     // Load out the index.
-    let mut file = File::open(index_path)?;
-    let mut buffer = Vec::new();
-    file.read_to_end(&mut buffer)?;
-    let index: Index = bincode::deserialize(&buffer[..]).unwrap();
+    let reader = IndexReader::open(Path::new(index_path))?;
 
     // Search by queries from stdin.
-    let searcher = Searcher::new(&index);
+    let searcher = reader.searcher();
     let stdin = std::io::stdin();
     for line in stdin.lock().lines() {
         let line = line?;
@@ -46,6 +46,16 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         // Only support TOP_10.
         let res = searcher.search(&query.query, 10);
         println!("{:?}", res.len());
+        for result in res {
+            if let Some(doc) = searcher.doc(result.doc_id) {
+                println!(
+                    "{}\t{}\t{}",
+                    doc.get_first("id").unwrap_or(""),
+                    result.score,
+                    doc.get_first("title").unwrap_or("")
+                );
+            }
+        }
     }
 
     Ok(())
@@ -1,45 +1,362 @@
-pub fn analyze(text: &String) -> Vec<Token> {
-    if text.len() == 0 {
-        return vec![];
-    }
-    text.split_whitespace()
-        .map(|t| Token {
-            token: t.to_string(),
-        })
-        .collect::<Vec<Token>>()
+pub mod dictionary;
+pub mod filter;
+pub mod synonym;
+pub mod tokenizer;
+
+use serde::{Deserialize, Serialize, Serializer};
+
+use self::dictionary::DictionaryConfig;
+use self::filter::{Algorithm, LengthFilter, LowercaseFilter, PunctuationFilter};
+use self::filter::{StemFilter, StopWordFilter, SynonymFilter};
+use self::synonym::SynonymMap;
+use self::tokenizer::{CjkBigramTokenizer, DictionaryTokenizer};
+use self::tokenizer::{UnicodeWordTokenizer, WhitespaceTokenizer};
+
+/// Splits `text` on whitespace, without any normalization.
+pub fn analyze(text: &str) -> Vec<Token> {
+    WhitespaceTokenizer.tokenize(text)
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Token {
     pub token: String,
+    pub surface: String, // the token as it appears in the text, before any filtering
+    pub position: u32,   // position of the token in the text, counted in tokens
+    pub position_length: u32, // number of positions the token spans, more than 1 for synonyms
+    pub offset_from: usize, // byte offset of the start of the token in the text
+    pub offset_to: usize, // byte offset of the end of the token in the text
+}
+
+/// Turns a text into the tokens that are indexed or searched for.
+pub trait Analyzer: std::fmt::Debug + Send + Sync {
+    fn analyze(&self, text: &str) -> Vec<Token>;
+}
+
+/// Splits a text into tokens.
+pub trait Tokenizer: std::fmt::Debug + Send + Sync {
+    fn tokenize(&self, text: &str) -> Vec<Token>;
+}
+
+/// Transforms, removes or adds tokens. Removed tokens leave a gap in the positions, so
+/// that phrases do not match across them.
+pub trait TokenFilter: std::fmt::Debug + Send + Sync {
+    fn filter(&self, tokens: Vec<Token>) -> Vec<Token>;
+}
+
+/// Serializable description of a tokenizer.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum TokenizerConfig {
+    Whitespace,
+    UnicodeWord,
+    CjkBigram,
+    Dictionary(DictionaryConfig), // stored as its source and checksum, not its words
+}
+
+/// Serializable description of a token filter.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum TokenFilterConfig {
+    Lowercase,
+    StripPunctuation,
+    StopWords(Vec<String>),
+    Length { min: usize, max: usize },
+    Stem(Algorithm),
+    Synonyms(SynonymMap),
+}
+
+impl TokenFilterConfig {
+    /// Stop word filter with a list of common English words.
+    pub fn english_stop_words() -> Self {
+        TokenFilterConfig::StopWords(
+            filter::ENGLISH_STOP_WORDS
+                .iter()
+                .map(|word| word.to_string())
+                .collect(),
+        )
+    }
+}
+
+/// Serializable description of an analyzer: a tokenizer followed by a chain of token
+/// filters. This is what an index stores, so that query-time analysis is always the
+/// same as index-time analysis.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AnalyzerConfig {
+    pub tokenizer: TokenizerConfig,
+    pub filters: Vec<TokenFilterConfig>,
+}
+
+impl AnalyzerConfig {
+    pub fn new(tokenizer: TokenizerConfig, filters: Vec<TokenFilterConfig>) -> Self {
+        AnalyzerConfig { tokenizer, filters }
+    }
+
+    pub fn build(&self) -> TextAnalyzer {
+        TextAnalyzer::from(self.clone())
+    }
+}
+
+impl Default for AnalyzerConfig {
+    /// Splits on whitespace without any normalization.
+    fn default() -> Self {
+        AnalyzerConfig::new(TokenizerConfig::Whitespace, vec![])
+    }
+}
+
+/// An analyzer made of a tokenizer and a chain of token filters, built from an
+/// `AnalyzerConfig`. It is serialized as its configuration.
+#[derive(Debug, Deserialize)]
+#[serde(from = "AnalyzerConfig")]
+pub struct TextAnalyzer {
+    config: AnalyzerConfig,
+    tokenizer: Box<dyn Tokenizer>,
+    filters: Vec<Box<dyn TokenFilter>>,
+}
+
+impl TextAnalyzer {
+    pub fn config(&self) -> &AnalyzerConfig {
+        &self.config
+    }
+}
+
+impl From<AnalyzerConfig> for TextAnalyzer {
+    fn from(config: AnalyzerConfig) -> Self {
+        let tokenizer: Box<dyn Tokenizer> = match &config.tokenizer {
+            TokenizerConfig::Whitespace => Box::new(WhitespaceTokenizer),
+            TokenizerConfig::UnicodeWord => Box::new(UnicodeWordTokenizer),
+            TokenizerConfig::CjkBigram => Box::new(CjkBigramTokenizer),
+            TokenizerConfig::Dictionary(dictionary) => {
+                Box::new(DictionaryTokenizer::new(dictionary.dictionary().clone()))
+            }
+        };
+        let filters = config
+            .filters
+            .iter()
+            .map(|filter| -> Box<dyn TokenFilter> {
+                match filter {
+                    TokenFilterConfig::Lowercase => Box::new(LowercaseFilter),
+                    TokenFilterConfig::StripPunctuation => Box::new(PunctuationFilter),
+                    TokenFilterConfig::StopWords(words) => Box::new(StopWordFilter::new(words)),
+                    TokenFilterConfig::Length { min, max } => {
+                        Box::new(LengthFilter::new(*min, *max))
+                    }
+                    TokenFilterConfig::Stem(algorithm) => Box::new(StemFilter::new(*algorithm)),
+                    TokenFilterConfig::Synonyms(synonyms) => {
+                        Box::new(SynonymFilter::new(synonyms.clone()))
+                    }
+                }
+            })
+            .collect();
+        TextAnalyzer {
+            config,
+            tokenizer,
+            filters,
+        }
+    }
+}
+
+impl Clone for TextAnalyzer {
+    fn clone(&self) -> Self {
+        self.config.build()
+    }
+}
+
+impl Default for TextAnalyzer {
+    fn default() -> Self {
+        TextAnalyzer::from(AnalyzerConfig::default())
+    }
+}
+
+impl Serialize for TextAnalyzer {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.config.serialize(serializer)
+    }
+}
+
+impl Analyzer for TextAnalyzer {
+    fn analyze(&self, text: &str) -> Vec<Token> {
+        self.filters
+            .iter()
+            .fold(self.tokenizer.tokenize(text), |tokens, filter| {
+                filter.filter(tokens)
+            })
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::analyze;
-    use super::Token;
+    use super::{analyze, Analyzer, AnalyzerConfig, SynonymMap, TextAnalyzer, Token};
+    use super::{Algorithm, TokenFilterConfig, TokenizerConfig};
 
     #[test]
     fn test_analyze() {
-        assert_eq!(analyze(&"".to_string()), vec![]);
-        assert_eq!(analyze(&" ".to_string()), vec![]);
-        assert_eq!(analyze(&"   ".to_string()), vec![]);
+        assert_eq!(analyze(""), vec![]);
+        assert_eq!(analyze(" "), vec![]);
+        assert_eq!(analyze("   "), vec![]);
         assert_eq!(
-            analyze(&"aaa bbb cc d".to_string()),
+            analyze("aaa bbb cc d"),
             vec![
                 Token {
-                    token: String::from("aaa")
+                    token: String::from("aaa"),
+                    surface: String::from("aaa"),
+                    position: 0,
+                    position_length: 1,
+                    offset_from: 0,
+                    offset_to: 3,
                 },
                 Token {
-                    token: String::from("bbb")
+                    token: String::from("bbb"),
+                    surface: String::from("bbb"),
+                    position: 1,
+                    position_length: 1,
+                    offset_from: 4,
+                    offset_to: 7,
                 },
                 Token {
-                    token: String::from("cc")
+                    token: String::from("cc"),
+                    surface: String::from("cc"),
+                    position: 2,
+                    position_length: 1,
+                    offset_from: 8,
+                    offset_to: 10,
                 },
                 Token {
-                    token: String::from("d")
+                    token: String::from("d"),
+                    surface: String::from("d"),
+                    position: 3,
+                    position_length: 1,
+                    offset_from: 11,
+                    offset_to: 12,
                 },
             ]
         );
     }
+
+    #[test]
+    fn test_text_analyzer() {
+        let analyzer = AnalyzerConfig::new(
+            TokenizerConfig::Whitespace,
+            vec![
+                TokenFilterConfig::StripPunctuation,
+                TokenFilterConfig::Lowercase,
+                TokenFilterConfig::english_stop_words(),
+                TokenFilterConfig::Length { min: 2, max: 10 },
+            ],
+        )
+        .build();
+        let tokens =
+            analyzer.analyze("Hello, World! The \"quick\" fox -- a supercalifragilistic x fox.");
+        assert_eq!(
+            tokens
+                .iter()
+                .map(|t| (t.token.as_str(), t.position))
+                .collect::<Vec<(&str, u32)>>(),
+            vec![
+                ("hello", 0),
+                ("world", 1),
+                ("quick", 3),
+                ("fox", 4),
+                ("fox", 9)
+            ]
+        );
+
+        // Serialized as its configuration.
+        let encoded = bincode::serialize(&analyzer).unwrap();
+        let decoded: TextAnalyzer = bincode::deserialize(&encoded).unwrap();
+        assert_eq!(decoded.config(), analyzer.config());
+        assert_eq!(
+            decoded.analyze("Hello, World!"),
+            analyzer.analyze("Hello, World!")
+        );
+
+        // Word boundaries instead of whitespace: punctuation is no longer attached to words.
+        let analyzer = AnalyzerConfig::new(
+            TokenizerConfig::UnicodeWord,
+            vec![TokenFilterConfig::Lowercase],
+        )
+        .build();
+        assert_eq!(
+            analyzer
+                .analyze("Hello, World!(again)")
+                .iter()
+                .map(|t| (t.token.as_str(), t.position))
+                .collect::<Vec<(&str, u32)>>(),
+            vec![("hello", 0), ("world", 1), ("again", 2)]
+        );
+
+        // The default analyzer only splits on whitespace.
+        assert_eq!(
+            TextAnalyzer::default().analyze("Hello, World!"),
+            analyze("Hello, World!")
+        );
+    }
+
+    #[test]
+    fn test_stemming() {
+        let analyzer = AnalyzerConfig::new(
+            TokenizerConfig::UnicodeWord,
+            vec![
+                TokenFilterConfig::Lowercase,
+                TokenFilterConfig::Stem(Algorithm::English),
+            ],
+        )
+        .build();
+        let text = "Running runs; he ran.";
+        let tokens = analyzer.analyze(text);
+        assert_eq!(
+            tokens
+                .iter()
+                .map(|t| t.token.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["run", "run", "he", "ran"]
+        );
+        // The surface form and offsets point back to the original text.
+        assert_eq!(tokens[0].surface, "Running");
+        assert_eq!(&text[tokens[1].offset_from..tokens[1].offset_to], "runs");
+
+        let analyzer = AnalyzerConfig::new(
+            TokenizerConfig::Whitespace,
+            vec![TokenFilterConfig::Stem(Algorithm::German)],
+        )
+        .build();
+        assert_eq!(
+            analyzer
+                .analyze("häuser haus")
+                .iter()
+                .map(|t| t.token.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["haus", "haus"]
+        );
+    }
+
+    #[test]
+    fn test_synonyms() {
+        let synonyms = SynonymMap::parse("tv, television\nnyc => new york city, nyc").unwrap();
+        let analyzer = AnalyzerConfig::new(
+            TokenizerConfig::Whitespace,
+            vec![
+                TokenFilterConfig::Lowercase,
+                TokenFilterConfig::Synonyms(synonyms),
+            ],
+        )
+        .build();
+
+        let tokens = analyzer.analyze("NYC TV shows");
+        assert_eq!(
+            tokens
+                .iter()
+                .map(|t| (t.token.as_str(), t.position, t.position_length))
+                .collect::<Vec<(&str, u32, u32)>>(),
+            vec![
+                ("new", 0, 1),
+                ("york", 1, 1),
+                ("city", 2, 1),
+                ("nyc", 0, 3),
+                ("tv", 3, 1),
+                ("television", 3, 1),
+                ("shows", 4, 1)
+            ]
+        );
+        // Synonyms point to the text they replace.
+        assert_eq!(tokens[1].surface, "NYC");
+        assert_eq!((tokens[1].offset_from, tokens[1].offset_to), (0, 3));
+    }
 }
@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+/// Cost of a word that is not in the dictionary. Higher than the cost of any reasonable
+/// entry, so that known words are always preferred.
+pub const UNKNOWN_WORD_COST: u32 = 10000;
+
+/// Cost of an entry without an explicit cost.
+pub const DEFAULT_WORD_COST: u32 = 3000;
+
+const JAPANESE: &str = include_str!("../../dict/ja.tsv");
+
+/// Words and their costs, used by `DictionaryTokenizer` to segment texts written without
+/// spaces. The lower the cost of a word, the more likely it is to be picked.
+///
+/// A dictionary file has one word per line, optionally followed by a tab and its cost.
+/// Empty lines and lines starting with `#` are ignored.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Dictionary {
+    words: HashMap<String, u32>,
+    max_word_len: usize, // length of the longest word, in chars
+}
+
+impl Dictionary {
+    pub fn new() -> Self {
+        Dictionary::default()
+    }
+
+    /// Reads a dictionary file.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Dictionary::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Parses the contents of a dictionary file.
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut dictionary = Dictionary::new();
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split('\t');
+            let word = fields.next().unwrap_or("").trim();
+            let cost = match fields.next().map(str::trim) {
+                Some(cost) => cost.parse::<u32>().map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("line {}: invalid cost {:?}", line_number + 1, cost),
+                    )
+                })?,
+                None => DEFAULT_WORD_COST,
+            };
+            if word.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: missing word", line_number + 1),
+                ));
+            }
+            dictionary.add(word, cost);
+        }
+        Ok(dictionary)
+    }
+
+    /// The small Japanese dictionary bundled with the crate.
+    pub fn japanese() -> Self {
+        Dictionary::parse(JAPANESE).expect("bundled dictionary is valid")
+    }
+
+    /// Adds a word, replacing its cost if it is already present.
+    pub fn add(&mut self, word: &str, cost: u32) {
+        self.max_word_len = self.max_word_len.max(word.chars().count());
+        self.words.insert(word.to_string(), cost);
+    }
+
+    pub fn cost(&self, word: &str) -> Option<u32> {
+        self.words.get(word).copied()
+    }
+
+    pub fn max_word_len(&self) -> usize {
+        self.max_word_len
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+}
+
+/// Where the words of a dictionary come from.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum DictionarySource {
+    Japanese,      // the dictionary bundled with the crate
+    File(PathBuf), // a dictionary file, resolved against the working directory if relative
+}
+
+impl DictionarySource {
+    /// Reads the contents of the dictionary.
+    fn read(&self) -> io::Result<String> {
+        match self {
+            DictionarySource::Japanese => Ok(JAPANESE.to_string()),
+            DictionarySource::File(path) => fs::read_to_string(path),
+        }
+    }
+}
+
+/// A dictionary and where it was loaded from. Only the source and a checksum of its
+/// contents are serialized: the dictionary is loaded again when the configuration is
+/// deserialized, and must not have changed since, so that queries are segmented like
+/// the indexed texts.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "DictionaryRef", into = "DictionaryRef")]
+pub struct DictionaryConfig {
+    source: DictionarySource,
+    checksum: u32, // CRC32 of the contents of the dictionary
+    dictionary: Arc<Dictionary>,
+}
+
+impl DictionaryConfig {
+    /// The small Japanese dictionary bundled with the crate.
+    pub fn japanese() -> Self {
+        DictionaryConfig::load(DictionarySource::Japanese).expect("bundled dictionary is valid")
+    }
+
+    /// Reads the dictionary file at `path`.
+    pub fn file(path: &Path) -> io::Result<Self> {
+        DictionaryConfig::load(DictionarySource::File(path.to_path_buf()))
+    }
+
+    fn load(source: DictionarySource) -> io::Result<Self> {
+        let text = source.read()?;
+        Ok(DictionaryConfig {
+            source,
+            checksum: crc32fast::hash(text.as_bytes()),
+            dictionary: Arc::new(Dictionary::parse(&text)?),
+        })
+    }
+
+    pub fn source(&self) -> &DictionarySource {
+        &self.source
+    }
+
+    pub fn checksum(&self) -> u32 {
+        self.checksum
+    }
+
+    pub fn dictionary(&self) -> &Arc<Dictionary> {
+        &self.dictionary
+    }
+}
+
+impl PartialEq for DictionaryConfig {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source && self.checksum == other.checksum
+    }
+}
+
+/// The serialized form of a `DictionaryConfig`.
+#[derive(Serialize, Deserialize)]
+struct DictionaryRef {
+    source: DictionarySource,
+    checksum: u32,
+}
+
+impl From<DictionaryConfig> for DictionaryRef {
+    fn from(config: DictionaryConfig) -> Self {
+        DictionaryRef {
+            source: config.source,
+            checksum: config.checksum,
+        }
+    }
+}
+
+impl TryFrom<DictionaryRef> for DictionaryConfig {
+    type Error = io::Error;
+
+    fn try_from(reference: DictionaryRef) -> io::Result<Self> {
+        let config = DictionaryConfig::load(reference.source)?;
+        if config.checksum != reference.checksum {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "dictionary {:?} has changed: expected checksum {:08x}, found {:08x}",
+                    config.source, reference.checksum, config.checksum
+                ),
+            ));
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Dictionary, DictionaryConfig, DEFAULT_WORD_COST};
+    use crate::testing::TempDir;
+
+    #[test]
+    fn test_parse() {
+        let dictionary = Dictionary::parse("# comment\n東京\t100\r\n\nタワー\n").unwrap();
+        assert_eq!(dictionary.len(), 2);
+        assert_eq!(dictionary.cost("東京"), Some(100));
+        assert_eq!(dictionary.cost("タワー"), Some(DEFAULT_WORD_COST));
+        assert_eq!(dictionary.cost("東"), None);
+        assert_eq!(dictionary.max_word_len(), 3);
+
+        assert!(Dictionary::parse("東京\tcheap").is_err());
+        assert!(Dictionary::parse("\t100").is_err());
+
+        assert!(!Dictionary::japanese().is_empty());
+    }
+
+    #[test]
+    fn test_dictionary_config() {
+        let directory = TempDir::new("dictionary");
+        let path = directory.join("words.tsv");
+        std::fs::write(&path, "東京\t100\n").unwrap();
+        let config = DictionaryConfig::file(&path).unwrap();
+        assert_eq!(config.dictionary().cost("東京"), Some(100));
+
+        // Only the source and checksum are serialized, and the file is read again.
+        let encoded = bincode::serialize(&config).unwrap();
+        assert!(encoded.len() < 100);
+        let decoded: DictionaryConfig = bincode::deserialize(&encoded).unwrap();
+        assert_eq!(decoded, config);
+        assert_eq!(decoded.dictionary().cost("東京"), Some(100));
+
+        // A dictionary that changed since it was serialized is rejected.
+        std::fs::write(&path, "東京\t200\n").unwrap();
+        assert!(bincode::deserialize::<DictionaryConfig>(&encoded).is_err());
+        std::fs::remove_file(&path).unwrap();
+        let missing = bincode::deserialize::<DictionaryConfig>(&encoded);
+        assert!(missing.is_err());
+
+        let japanese = DictionaryConfig::japanese();
+        let encoded = bincode::serialize(&japanese).unwrap();
+        assert!(encoded.len() < 100);
+        assert_eq!(
+            bincode::deserialize::<DictionaryConfig>(&encoded).unwrap(),
+            japanese
+        );
+    }
+}
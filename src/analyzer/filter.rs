@@ -0,0 +1,240 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use rust_stemmers::Stemmer;
+
+use super::synonym::SynonymMap;
+use super::{Token, TokenFilter};
+
+pub use rust_stemmers::Algorithm;
+
+/// Common English words that carry little meaning on their own.
+pub const ENGLISH_STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "if", "in", "into", "is", "it",
+    "no", "not", "of", "on", "or", "such", "that", "the", "their", "then", "there", "these",
+    "they", "this", "to", "was", "will", "with",
+];
+
+/// Lowercases tokens.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LowercaseFilter;
+
+impl TokenFilter for LowercaseFilter {
+    fn filter(&self, tokens: Vec<Token>) -> Vec<Token> {
+        tokens
+            .into_iter()
+            .map(|token| Token {
+                token: token.token.to_lowercase(),
+                ..token
+            })
+            .collect()
+    }
+}
+
+/// Strips punctuation from tokens, and removes the tokens made of punctuation only.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PunctuationFilter;
+
+impl TokenFilter for PunctuationFilter {
+    fn filter(&self, tokens: Vec<Token>) -> Vec<Token> {
+        tokens
+            .into_iter()
+            .filter_map(|token| {
+                let stripped = token
+                    .token
+                    .chars()
+                    .filter(|c| !c.is_ascii_punctuation() && !c.is_whitespace())
+                    .collect::<String>();
+                if stripped.is_empty() {
+                    None
+                } else {
+                    Some(Token {
+                        token: stripped,
+                        ..token
+                    })
+                }
+            })
+            .collect()
+    }
+}
+
+/// Removes the tokens that are in a stop word list.
+#[derive(Clone, Debug, Default)]
+pub struct StopWordFilter {
+    words: HashSet<String>,
+}
+
+impl StopWordFilter {
+    pub fn new<S: AsRef<str>>(words: &[S]) -> Self {
+        StopWordFilter {
+            words: words.iter().map(|word| word.as_ref().to_string()).collect(),
+        }
+    }
+}
+
+impl TokenFilter for StopWordFilter {
+    fn filter(&self, tokens: Vec<Token>) -> Vec<Token> {
+        tokens
+            .into_iter()
+            .filter(|token| !self.words.contains(&token.token))
+            .collect()
+    }
+}
+
+/// Removes the tokens shorter than `min` or longer than `max` characters.
+#[derive(Clone, Copy, Debug)]
+pub struct LengthFilter {
+    min: usize,
+    max: usize,
+}
+
+impl LengthFilter {
+    pub fn new(min: usize, max: usize) -> Self {
+        LengthFilter { min, max }
+    }
+}
+
+impl TokenFilter for LengthFilter {
+    fn filter(&self, tokens: Vec<Token>) -> Vec<Token> {
+        tokens
+            .into_iter()
+            .filter(|token| {
+                let length = token.token.chars().count();
+                self.min <= length && length <= self.max
+            })
+            .collect()
+    }
+}
+
+/// Reduces tokens to their stem with a Snowball stemmer, e.g. "running" and "runs" to
+/// "run" with the English (Porter2) algorithm. Irregular forms such as "ran" are left
+/// as they are. Stemmers expect lowercase input, so this filter should come after a
+/// `LowercaseFilter`.
+pub struct StemFilter {
+    algorithm: Algorithm,
+    stemmer: Stemmer,
+}
+
+impl StemFilter {
+    pub fn new(algorithm: Algorithm) -> Self {
+        StemFilter {
+            algorithm,
+            stemmer: Stemmer::create(algorithm),
+        }
+    }
+}
+
+impl fmt::Debug for StemFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StemFilter")
+            .field("algorithm", &self.algorithm)
+            .finish()
+    }
+}
+
+impl TokenFilter for StemFilter {
+    fn filter(&self, tokens: Vec<Token>) -> Vec<Token> {
+        tokens
+            .into_iter()
+            .map(|token| Token {
+                token: self.stemmer.stem(&token.token).into_owned(),
+                ..token
+            })
+            .collect()
+    }
+}
+
+/// Adds synonyms from a `SynonymMap`. At each token, the longest sequence of tokens with
+/// a rule is replaced by its synonyms, stacked at the same position. A synonym of several
+/// words takes consecutive positions, and the last token of the shorter alternatives gets
+/// a `position_length` that makes every alternative end at the same position. The
+/// following tokens are shifted to make room for the longest alternative.
+#[derive(Clone, Debug)]
+pub struct SynonymFilter {
+    synonyms: SynonymMap,
+}
+
+impl SynonymFilter {
+    pub fn new(synonyms: SynonymMap) -> Self {
+        SynonymFilter { synonyms }
+    }
+}
+
+impl TokenFilter for SynonymFilter {
+    fn filter(&self, tokens: Vec<Token>) -> Vec<Token> {
+        let words = tokens
+            .iter()
+            .map(|token| token.token.clone())
+            .collect::<Vec<String>>();
+        let mut filtered = Vec::with_capacity(tokens.len());
+        let mut shift = 0;
+        let mut i = 0;
+        while i < tokens.len() {
+            let rule = (1..=self.synonyms.max_len().min(tokens.len() - i))
+                .rev()
+                .find_map(|n| Some((n, self.synonyms.get(&words[i..i + n])?)));
+            let (n, synonyms) = match rule {
+                Some(rule) => rule,
+                None => {
+                    filtered.push(Token {
+                        position: tokens[i].position + shift,
+                        ..tokens[i].clone()
+                    });
+                    i += 1;
+                    continue;
+                }
+            };
+
+            let matched = &tokens[i..i + n];
+            let (first, last) = (&matched[0], &matched[n - 1]);
+            let start = first.position + shift;
+            let matched_len = last.position + last.position_length - first.position;
+            let len = synonyms
+                .iter()
+                .map(|synonym| synonym.len() as u32)
+                .fold(matched_len, u32::max);
+            let surface = matched
+                .iter()
+                .map(|token| token.surface.as_str())
+                .collect::<Vec<&str>>()
+                .join(" ");
+            for synonym in synonyms {
+                if synonym.as_slice() == &words[i..i + n] {
+                    // The matched tokens are one of their own synonyms: keep them as they
+                    // are, only stretching the last one.
+                    for (k, token) in matched.iter().enumerate() {
+                        let position_length = if k == n - 1 {
+                            token.position_length + len - matched_len
+                        } else {
+                            token.position_length
+                        };
+                        filtered.push(Token {
+                            position: token.position + shift,
+                            position_length,
+                            ..token.clone()
+                        });
+                    }
+                    continue;
+                }
+                for (k, word) in synonym.iter().enumerate() {
+                    let k = k as u32;
+                    filtered.push(Token {
+                        token: word.clone(),
+                        surface: surface.clone(),
+                        position: start + k,
+                        position_length: if k + 1 == synonym.len() as u32 {
+                            len - k
+                        } else {
+                            1
+                        },
+                        offset_from: first.offset_from,
+                        offset_to: last.offset_to,
+                    });
+                }
+            }
+            shift += len - matched_len;
+            i += n;
+        }
+        filtered
+    }
+}
@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+/// Maps sequences of words to their synonyms, read from a file in the Solr synonyms
+/// format. Each line holds one rule:
+///
+/// - `tv, television, telly`: the words are equivalent, and each of them is expanded to
+///   all of them.
+/// - `nyc, big apple => new york city`: the words on the left are replaced by the words
+///   on the right. A word is only kept if it also appears on the right.
+///
+/// Synonyms may span several words. Empty lines and lines starting with `#` are ignored.
+/// Words are compared to tokens as they are, so the rules should be written the way
+/// tokens look when they reach the `SynonymFilter`, e.g. lowercased.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct SynonymMap {
+    rules: HashMap<Vec<String>, Vec<Vec<String>>>,
+    max_len: usize, // number of words of the longest left-hand side
+}
+
+impl SynonymMap {
+    pub fn new() -> Self {
+        SynonymMap::default()
+    }
+
+    /// Reads a synonyms file.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        SynonymMap::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Parses the contents of a synonyms file.
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut map = SynonymMap::new();
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: invalid synonym rule {:?}", line_number + 1, line),
+                )
+            };
+            let mut sides = line.split("=>");
+            let left = parse_words(sides.next().unwrap_or("")).ok_or_else(invalid)?;
+            match (sides.next(), sides.next()) {
+                (None, _) => {
+                    for words in &left {
+                        map.add(words, &left);
+                    }
+                }
+                (Some(right), None) => {
+                    let right = parse_words(right).ok_or_else(invalid)?;
+                    for words in &left {
+                        map.add(words, &right);
+                    }
+                }
+                (Some(_), Some(_)) => return Err(invalid()),
+            }
+        }
+        Ok(map)
+    }
+
+    /// Adds `synonyms` to the synonyms of `words`.
+    pub fn add(&mut self, words: &[String], synonyms: &[Vec<String>]) {
+        self.max_len = self.max_len.max(words.len());
+        let entry = self.rules.entry(words.to_vec()).or_default();
+        for synonym in synonyms {
+            if !entry.contains(synonym) {
+                entry.push(synonym.clone());
+            }
+        }
+    }
+
+    /// The synonyms of `words`, if there is a rule for them.
+    pub fn get(&self, words: &[String]) -> Option<&[Vec<String>]> {
+        self.rules.get(words).map(|synonyms| synonyms.as_slice())
+    }
+
+    pub fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+}
+
+/// Splits a comma separated list of synonyms into their words. Returns `None` if the list
+/// contains an empty synonym.
+fn parse_words(list: &str) -> Option<Vec<Vec<String>>> {
+    list.split(',')
+        .map(|synonym| {
+            let words = synonym
+                .split_whitespace()
+                .map(|word| word.to_string())
+                .collect::<Vec<String>>();
+            if words.is_empty() {
+                None
+            } else {
+                Some(words)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SynonymMap;
+
+    fn words(text: &str) -> Vec<String> {
+        text.split_whitespace()
+            .map(|word| word.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let map = SynonymMap::parse(
+            "# comment\n\ntv, television\nnyc, big apple => new york city\ntv => telly\n",
+        )
+        .unwrap();
+        assert_eq!(
+            map.get(&words("tv")).unwrap(),
+            &[words("tv"), words("television"), words("telly")]
+        );
+        assert_eq!(
+            map.get(&words("television")).unwrap(),
+            &[words("tv"), words("television")]
+        );
+        assert_eq!(
+            map.get(&words("big apple")).unwrap(),
+            &[words("new york city")]
+        );
+        assert_eq!(map.get(&words("new york city")), None);
+        assert_eq!(map.max_len(), 2);
+
+        assert!(SynonymMap::parse("a, , b").is_err());
+        assert!(SynonymMap::parse("a => ").is_err());
+        assert!(SynonymMap::parse("a => b => c").is_err());
+    }
+}
@@ -0,0 +1,387 @@
+use std::sync::Arc;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::dictionary::{Dictionary, UNKNOWN_WORD_COST};
+use super::{Token, Tokenizer};
+
+/// Splits a text on whitespace.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WhitespaceTokenizer;
+
+impl Tokenizer for WhitespaceTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<Token> {
+        let mut tokens = Vec::new();
+        let mut start = None;
+        for (offset, c) in text
+            .char_indices()
+            .chain(std::iter::once((text.len(), ' ')))
+        {
+            match (start, c.is_whitespace()) {
+                (None, false) => start = Some(offset),
+                (Some(offset_from), true) => {
+                    tokens.push(Token {
+                        token: text[offset_from..offset].to_string(),
+                        surface: text[offset_from..offset].to_string(),
+                        position: tokens.len() as u32,
+                        position_length: 1,
+                        offset_from,
+                        offset_to: offset,
+                    });
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        tokens
+    }
+}
+
+/// Splits a text into words following the Unicode word boundary rules (UAX #29).
+/// Punctuation and whitespace between words is dropped, while words such as "can't" or
+/// "3.14" are kept whole. Scripts written without spaces are split where the rules allow
+/// it, e.g. into single ideographs for Chinese.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UnicodeWordTokenizer;
+
+impl Tokenizer for UnicodeWordTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<Token> {
+        text.split_word_bound_indices()
+            .filter(|(_, word)| word.chars().any(char::is_alphanumeric))
+            .enumerate()
+            .map(|(position, (offset, word))| Token {
+                token: word.to_string(),
+                surface: word.to_string(),
+                position: position as u32,
+                position_length: 1,
+                offset_from: offset,
+                offset_to: offset + word.len(),
+            })
+            .collect()
+    }
+}
+
+/// Splits runs of Chinese, Japanese and Korean characters into overlapping bigrams, so
+/// that texts written without spaces can be searched without a dictionary. A run of a
+/// single character is kept as a unigram. Text in other scripts is split into words as
+/// by `UnicodeWordTokenizer`.
+///
+/// Only bigrams are indexed, so a query for a single character only matches the
+/// documents in which it stands alone.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CjkBigramTokenizer;
+
+impl Tokenizer for CjkBigramTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<Token> {
+        let mut tokens = Vec::new();
+        let mut push = |offset_from: usize, offset_to: usize| {
+            tokens.push(Token {
+                token: text[offset_from..offset_to].to_string(),
+                surface: text[offset_from..offset_to].to_string(),
+                position: tokens.len() as u32,
+                position_length: 1,
+                offset_from,
+                offset_to,
+            })
+        };
+
+        let chars = text.char_indices().collect::<Vec<(usize, char)>>();
+        let mut start = 0;
+        while start < chars.len() {
+            let cjk = char_class(chars[start].1).is_cjk();
+            let end = start
+                + chars[start..]
+                    .iter()
+                    .take_while(|(_, c)| char_class(*c).is_cjk() == cjk)
+                    .count();
+            let offset_from = chars[start].0;
+            let offset_to = chars.get(end).map_or(text.len(), |(offset, _)| *offset);
+            if !cjk {
+                for (offset, word) in text[offset_from..offset_to].split_word_bound_indices() {
+                    if word.chars().any(char::is_alphanumeric) {
+                        push(offset_from + offset, offset_from + offset + word.len());
+                    }
+                }
+            } else if end - start == 1 {
+                push(offset_from, offset_to);
+            } else {
+                for i in start..end - 1 {
+                    let (offset, c) = chars[i + 1];
+                    push(chars[i].0, offset + c.len_utf8());
+                }
+            }
+            start = end;
+        }
+        tokens
+    }
+}
+
+/// Segments a text into the sequence of dictionary words with the lowest total cost, as
+/// morphological analyzers such as MeCab do for Japanese. Characters that no dictionary
+/// word covers become unknown words: runs of katakana, hangul or other letters and
+/// digits are kept together, while kanji and hiragana stand alone. Whitespace and
+/// punctuation separate words and are not emitted.
+#[derive(Clone, Debug, Default)]
+pub struct DictionaryTokenizer {
+    dictionary: Arc<Dictionary>,
+}
+
+impl DictionaryTokenizer {
+    pub fn new(dictionary: Arc<Dictionary>) -> Self {
+        DictionaryTokenizer { dictionary }
+    }
+}
+
+impl Tokenizer for DictionaryTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<Token> {
+        let chars = text.char_indices().collect::<Vec<(usize, char)>>();
+        let offset = |i: usize| chars.get(i).map_or(text.len(), |(offset, _)| *offset);
+
+        // best[i]: lowest cost of segmenting the first i chars, with the start of the
+        // last segment and whether that segment is a word.
+        let mut best: Vec<Option<(u64, usize, bool)>> = vec![None; chars.len() + 1];
+        best[0] = Some((0, 0, false));
+        for start in 0..chars.len() {
+            let cost = match best[start] {
+                Some((cost, _, _)) => cost,
+                None => continue,
+            };
+            let mut relax = |end: usize, word_cost: u32, is_word: bool| {
+                let cost = cost + word_cost as u64;
+                if best[end].is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                    best[end] = Some((cost, start, is_word));
+                }
+            };
+
+            let class = char_class(chars[start].1);
+            if class == CharClass::Whitespace {
+                relax(start + 1, 0, false);
+                continue;
+            }
+            let max_end = chars.len().min(start + self.dictionary.max_word_len());
+            for end in start + 1..=max_end {
+                if let Some(word_cost) = self.dictionary.cost(&text[offset(start)..offset(end)]) {
+                    relax(end, word_cost, true);
+                }
+            }
+            let end = if class.groups_unknown_words() {
+                start
+                    + chars[start..]
+                        .iter()
+                        .take_while(|(_, c)| char_class(*c) == class)
+                        .count()
+            } else {
+                start + 1
+            };
+            relax(end, UNKNOWN_WORD_COST, true);
+        }
+
+        let mut words = Vec::new();
+        let mut end = chars.len();
+        while end > 0 {
+            let (_, start, is_word) = best[end].expect("every char is reachable");
+            if is_word {
+                words.push((offset(start), offset(end)));
+            }
+            end = start;
+        }
+        words
+            .into_iter()
+            .rev()
+            .filter(|&(offset_from, offset_to)| {
+                text[offset_from..offset_to]
+                    .chars()
+                    .any(char::is_alphanumeric)
+            })
+            .enumerate()
+            .map(|(position, (offset_from, offset_to))| Token {
+                token: text[offset_from..offset_to].to_string(),
+                surface: text[offset_from..offset_to].to_string(),
+                position: position as u32,
+                position_length: 1,
+                offset_from,
+                offset_to,
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CharClass {
+    Whitespace,
+    Han,
+    Hiragana,
+    Katakana,
+    Hangul,
+    Alphanumeric, // letters and digits of the other scripts
+    Other,
+}
+
+impl CharClass {
+    fn is_cjk(self) -> bool {
+        matches!(
+            self,
+            CharClass::Han | CharClass::Hiragana | CharClass::Katakana | CharClass::Hangul
+        )
+    }
+
+    /// Whether consecutive unknown characters of this class form a single word.
+    fn groups_unknown_words(self) -> bool {
+        matches!(
+            self,
+            CharClass::Katakana | CharClass::Hangul | CharClass::Alphanumeric
+        )
+    }
+}
+
+fn char_class(c: char) -> CharClass {
+    match c {
+        _ if c.is_whitespace() => CharClass::Whitespace,
+        '\u{3005}'
+        | '\u{3400}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{F900}'..='\u{FAFF}' => CharClass::Han,
+        '\u{3041}'..='\u{309F}' => CharClass::Hiragana,
+        '\u{30A0}'..='\u{30FF}' | '\u{31F0}'..='\u{31FF}' | '\u{FF66}'..='\u{FF9F}' => {
+            CharClass::Katakana
+        }
+        '\u{1100}'..='\u{11FF}' | '\u{3130}'..='\u{318F}' | '\u{AC00}'..='\u{D7AF}' => {
+            CharClass::Hangul
+        }
+        _ if c.is_alphanumeric() => CharClass::Alphanumeric,
+        _ => CharClass::Other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{CjkBigramTokenizer, DictionaryTokenizer, Tokenizer};
+    use super::{UnicodeWordTokenizer, WhitespaceTokenizer};
+    use crate::analyzer::dictionary::Dictionary;
+
+    fn tokenize(tokenizer: &dyn Tokenizer, text: &str) -> Vec<(String, u32, usize, usize)> {
+        tokenizer
+            .tokenize(text)
+            .into_iter()
+            .map(|t| (t.token, t.position, t.offset_from, t.offset_to))
+            .collect()
+    }
+
+    #[test]
+    fn test_whitespace_tokenizer() {
+        assert_eq!(
+            tokenize(&WhitespaceTokenizer, " Hello,\u{3000}wörld! "),
+            vec![
+                ("Hello,".to_string(), 0, 1, 7),
+                ("wörld!".to_string(), 1, 10, 17),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unicode_word_tokenizer() {
+        let text = "Hello, wörld! Can't stop at 3.14 -- ok?";
+        let tokens = tokenize(&UnicodeWordTokenizer, text);
+        assert_eq!(
+            tokens
+                .iter()
+                .map(|(token, position, _, _)| (token.as_str(), *position))
+                .collect::<Vec<(&str, u32)>>(),
+            vec![
+                ("Hello", 0),
+                ("wörld", 1),
+                ("Can't", 2),
+                ("stop", 3),
+                ("at", 4),
+                ("3.14", 5),
+                ("ok", 6),
+            ]
+        );
+        for (token, _, offset_from, offset_to) in &tokens {
+            assert_eq!(&text[*offset_from..*offset_to], token);
+        }
+        assert_eq!((tokens[1].2, tokens[1].3), (7, 13));
+
+        // Ideographs are split into single characters.
+        assert_eq!(
+            tokenize(&UnicodeWordTokenizer, "東京タワー"),
+            vec![
+                ("東".to_string(), 0, 0, 3),
+                ("京".to_string(), 1, 3, 6),
+                ("タワー".to_string(), 2, 6, 15),
+            ]
+        );
+
+        assert!(UnicodeWordTokenizer.tokenize("").is_empty());
+        assert!(UnicodeWordTokenizer.tokenize(" -- !? ").is_empty());
+    }
+
+    #[test]
+    fn test_cjk_bigram_tokenizer() {
+        assert_eq!(
+            tokenize(&CjkBigramTokenizer, "東京タワー, Tokyo 塔。"),
+            vec![
+                ("東京".to_string(), 0, 0, 6),
+                ("京タ".to_string(), 1, 3, 9),
+                ("タワ".to_string(), 2, 6, 12),
+                ("ワー".to_string(), 3, 9, 15),
+                ("Tokyo".to_string(), 4, 17, 22),
+                ("塔".to_string(), 5, 23, 26),
+            ]
+        );
+        assert!(CjkBigramTokenizer.tokenize("").is_empty());
+    }
+
+    #[test]
+    fn test_dictionary_tokenizer() {
+        let tokenizer = DictionaryTokenizer::new(Arc::new(Dictionary::japanese()));
+        let words = |text: &str| {
+            tokenizer
+                .tokenize(text)
+                .into_iter()
+                .map(|t| t.token)
+                .collect::<Vec<String>>()
+        };
+
+        assert_eq!(
+            words("東京タワーに行きました。"),
+            vec!["東京", "タワー", "に", "行き", "まし", "た"]
+        );
+        assert_eq!(
+            words("私は日本語の辞書を調べました"),
+            vec![
+                "私",
+                "は",
+                "日本語",
+                "の",
+                "辞書",
+                "を",
+                "調べ",
+                "まし",
+                "た"
+            ]
+        );
+        // The cheapest segmentation wins: a compound over its parts.
+        assert_eq!(words("全文検索エンジン"), vec!["全文検索", "エンジン"]);
+        // Unknown words: runs of katakana and latin letters stay whole.
+        assert_eq!(
+            words("シェルティはRust製 です"),
+            vec!["シェルティ", "は", "Rust", "製", "です"]
+        );
+
+        let text = "私は 東京";
+        let tokens = tokenizer.tokenize(text);
+        assert_eq!(
+            tokens
+                .iter()
+                .map(|t| (t.position, &text[t.offset_from..t.offset_to]))
+                .collect::<Vec<(u32, &str)>>(),
+            vec![(0, "私"), (1, "は"), (2, "東京")]
+        );
+
+        assert!(tokenizer.tokenize("").is_empty());
+        assert_eq!(DictionaryTokenizer::default().tokenize("漢字").len(), 2);
+    }
+}
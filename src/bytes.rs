@@ -0,0 +1,153 @@
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::ops::{Deref, Range};
+use std::path::Path;
+use std::sync::Arc;
+
+use memmap2::Mmap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// A byte buffer, either owned or a range of a memory-mapped file. Mapped bytes are only
+/// read from disk when they are accessed, and cloning or slicing them does not copy them.
+#[derive(Clone)]
+pub enum Bytes {
+    Owned(Vec<u8>),
+    Mapped {
+        mmap: Arc<Mmap>,
+        range: Range<usize>,
+    },
+}
+
+impl Bytes {
+    pub fn new() -> Self {
+        Bytes::Owned(vec![])
+    }
+
+    /// Maps the file at `path` into memory.
+    pub fn map(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // SAFETY: index files are written to a temporary file which is then renamed, and
+        // are never modified once in place, so the mapped bytes cannot change. Removing
+        // the file keeps the mapping valid.
+        let mmap = unsafe { Mmap::map(&file)? };
+        let range = 0..mmap.len();
+        Ok(Bytes::Mapped {
+            mmap: Arc::new(mmap),
+            range,
+        })
+    }
+
+    /// The bytes in `range`, or `None` if it is out of bounds. Mapped bytes share the
+    /// mapping.
+    pub fn slice(&self, range: Range<usize>) -> Option<Bytes> {
+        if range.start > range.end || range.end > self.len() {
+            return None;
+        }
+        Some(match self {
+            Bytes::Owned(bytes) => Bytes::Owned(bytes[range].to_vec()),
+            Bytes::Mapped { mmap, range: outer } => Bytes::Mapped {
+                mmap: Arc::clone(mmap),
+                range: outer.start + range.start..outer.start + range.end,
+            },
+        })
+    }
+
+    /// The bytes as a mutable vector, copying mapped bytes into memory first.
+    pub fn to_mut(&mut self) -> &mut Vec<u8> {
+        if let Bytes::Mapped { .. } = self {
+            *self = Bytes::Owned(self.to_vec());
+        }
+        match self {
+            Bytes::Owned(bytes) => bytes,
+            Bytes::Mapped { .. } => unreachable!(),
+        }
+    }
+}
+
+impl Default for Bytes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for Bytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Bytes::Owned(bytes) => bytes,
+            Bytes::Mapped { mmap, range } => &mmap[range.clone()],
+        }
+    }
+}
+
+impl AsRef<[u8]> for Bytes {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl From<Vec<u8>> for Bytes {
+    fn from(bytes: Vec<u8>) -> Self {
+        Bytes::Owned(bytes)
+    }
+}
+
+impl fmt::Debug for Bytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            Bytes::Owned(_) => "Owned",
+            Bytes::Mapped { .. } => "Mapped",
+        };
+        write!(f, "Bytes::{}({} bytes)", kind, self.len())
+    }
+}
+
+impl Serialize for Bytes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.deref().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Bytes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::deserialize(deserializer).map(Bytes::Owned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Bytes;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn test_bytes() {
+        let directory = TempDir::new("bytes");
+        let path = directory.join("bytes");
+        std::fs::write(&path, b"sheltie").unwrap();
+        let bytes = Bytes::map(&path).unwrap();
+        // The mapping outlives the file.
+        drop(directory);
+        assert_eq!(&bytes[..], b"sheltie");
+
+        let mut slice = bytes.slice(1..5).unwrap();
+        assert!(matches!(slice, Bytes::Mapped { .. }));
+        assert_eq!(&slice[..], b"helt");
+        assert_eq!(&slice.slice(2..4).unwrap()[..], b"lt");
+        assert!(slice.slice(3..5).is_none());
+        #[allow(clippy::reversed_empty_ranges)]
+        let reversed = slice.slice(3..2);
+        assert!(reversed.is_none());
+
+        slice.to_mut().push(b'!');
+        assert!(matches!(slice, Bytes::Owned(_)));
+        assert_eq!(&slice[..], b"helt!");
+        assert_eq!(&bytes[..], b"sheltie");
+
+        let encoded = bincode::serialize(&slice).unwrap();
+        assert_eq!(encoded, bincode::serialize(&b"helt!".to_vec()).unwrap());
+        let decoded: Bytes = bincode::deserialize(&encoded).unwrap();
+        assert_eq!(&decoded[..], b"helt!");
+    }
+}
@@ -0,0 +1,53 @@
+/// Appends `value` to `out` in variable-byte encoding: 7 bits per byte, least significant
+/// group first, with the high bit set on every byte but the last.
+pub fn encode_vbyte(mut value: u64, out: &mut Vec<u8>) {
+    while value >= 0x80 {
+        out.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Decodes a variable-byte encoded value starting at `*offset` and moves `*offset` past
+/// it. Returns `None` if the data ends in the middle of a value.
+pub fn decode_vbyte(data: &[u8], offset: &mut usize) -> Option<u64> {
+    let mut value = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = *data.get(*offset)?;
+        *offset += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+        shift += 7;
+        if shift >= 64 {
+            return None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_vbyte, encode_vbyte};
+
+    #[test]
+    fn test_vbyte() {
+        let values = [0, 1, 127, 128, 300, 16383, 16384, u32::MAX as u64, u64::MAX];
+        let mut data = Vec::new();
+        for &value in &values {
+            encode_vbyte(value, &mut data);
+        }
+        assert_eq!(data[..5], [0, 1, 127, 0x80, 0x01]);
+
+        let mut offset = 0;
+        for &value in &values {
+            assert_eq!(decode_vbyte(&data, &mut offset), Some(value));
+        }
+        assert_eq!(offset, data.len());
+        assert_eq!(decode_vbyte(&data, &mut offset), None);
+
+        let mut offset = 0;
+        assert_eq!(decode_vbyte(&[0x80, 0x80], &mut offset), None);
+    }
+}
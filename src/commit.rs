@@ -0,0 +1,383 @@
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::IndexError;
+use crate::format::FileKind;
+use crate::index::{read_file, Index};
+use crate::searcher::Searcher;
+
+/// Name of the file listing the segments of the last commit of an index.
+pub const MANIFEST_FILE: &str = "manifest";
+
+/// Extension of the files holding the document lengths and unique keys of a segment.
+pub const SEGMENT_EXTENSION: &str = "doc";
+
+/// Extension of the files holding the term dictionaries and postings lists of a segment,
+/// which are memory-mapped when the segment is read.
+pub const POSTINGS_EXTENSION: &str = "post";
+
+/// Extension of the files holding the stored fields of a segment, which are
+/// memory-mapped when the segment is read.
+pub const STORED_FIELDS_EXTENSION: &str = "store";
+
+/// Extension of the files holding the live docs of a segment.
+pub const LIVE_DOCS_EXTENSION: &str = "liv";
+
+/// Extension of the temporary files that index files are written to before being renamed.
+/// A writer that crashed may leave some behind.
+pub const TEMP_EXTENSION: &str = "tmp";
+
+/// The segments of a commit of an index, written to `MANIFEST_FILE`. Segments are never
+/// modified once written, except for their deletions, which are written to a new live
+/// docs file on each commit that changes them. Replacing the manifest publishes all the
+/// changes of a commit at once.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Manifest {
+    pub(crate) generation: u64,   // number of commits
+    pub(crate) next_segment: u64, // number of the next segment, used to name it
+    pub(crate) segments: Vec<SegmentInfo>,
+}
+
+impl Manifest {
+    /// Reads the manifest of the index in `directory_path`.
+    pub fn open(directory_path: &Path) -> Result<Self, IndexError> {
+        read_file(&directory_path.join(MANIFEST_FILE), FileKind::Manifest).map_err(
+            |err| match err {
+                IndexError::Io { source, .. } if source.kind() == io::ErrorKind::NotFound => {
+                    IndexError::NotFound(directory_path.to_path_buf())
+                }
+                err => err,
+            },
+        )
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn segments(&self) -> &[SegmentInfo] {
+        &self.segments
+    }
+}
+
+/// A segment of a commit, and the commit that wrote its current live docs.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SegmentInfo {
+    pub(crate) name: String,
+    pub(crate) live_docs_generation: Option<u64>, // `None` if no document was deleted
+}
+
+impl SegmentInfo {
+    pub(crate) fn new(number: u64) -> Self {
+        SegmentInfo {
+            name: format!("_{}", number),
+            live_docs_generation: None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn segment_file(&self) -> String {
+        format!("{}.{}", self.name, SEGMENT_EXTENSION)
+    }
+
+    pub fn postings_file(&self) -> String {
+        format!("{}.{}", self.name, POSTINGS_EXTENSION)
+    }
+
+    pub fn stored_fields_file(&self) -> String {
+        format!("{}.{}", self.name, STORED_FIELDS_EXTENSION)
+    }
+
+    pub fn live_docs_file(&self) -> Option<String> {
+        self.live_docs_generation
+            .map(|generation| format!("{}_{}.{}", self.name, generation, LIVE_DOCS_EXTENSION))
+    }
+
+    /// The names of all the files of the segment.
+    pub fn files(&self) -> Vec<String> {
+        let mut files = vec![
+            self.segment_file(),
+            self.postings_file(),
+            self.stored_fields_file(),
+        ];
+        files.extend(self.live_docs_file());
+        files
+    }
+}
+
+/// The segments of the last commit of an index, read from disk. Documents are numbered
+/// across segments: the doc_ids of a segment follow those of the segments before it.
+#[derive(Debug)]
+pub struct Commit {
+    manifest: Manifest,
+    segments: Vec<Index>,
+}
+
+impl Commit {
+    /// Reads the last commit of the index written by an `IndexWriter` to
+    /// `directory_path`.
+    ///
+    /// A writer only removes the files of a commit once it has published a newer one, so
+    /// a missing file means that the commit being read was replaced meanwhile, and the
+    /// new one is read instead. The files of a commit that is still the last one are never
+    /// missing, even after a writer crashed: the files it was writing are not referenced
+    /// by any manifest yet.
+    ///
+    /// Fails with `IndexError::NotFound` if the directory holds no index, and with
+    /// `IndexError::Corrupted` if a file of the last commit is missing or invalid.
+    pub fn open(directory_path: &Path) -> Result<Self, IndexError> {
+        let mut manifest = Manifest::open(directory_path)?;
+        loop {
+            let segments = manifest
+                .segments
+                .iter()
+                .map(|info| Index::read_segment(directory_path, info))
+                .collect::<Result<Vec<Index>, IndexError>>();
+            match segments {
+                Ok(segments) => return Ok(Commit { manifest, segments }),
+                Err(IndexError::Io { path, source })
+                    if source.kind() == io::ErrorKind::NotFound =>
+                {
+                    let last = Manifest::open(directory_path)?;
+                    if last.generation == manifest.generation {
+                        return Err(IndexError::corrupted(path, "missing file"));
+                    }
+                    manifest = last;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.manifest.generation
+    }
+
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+
+    pub fn segments(&self) -> &[Index] {
+        &self.segments
+    }
+
+    /// Number of live documents in all segments.
+    pub fn doc_count(&self) -> usize {
+        self.segments.iter().map(Index::doc_count).sum()
+    }
+
+    /// Verifies the checksums of the postings and stored fields files of all the segments,
+    /// which are not verified when the commit is opened. See `Index::check_integrity`.
+    pub fn check_integrity(&self) -> Result<(), IndexError> {
+        self.segments.iter().try_for_each(Index::check_integrity)
+    }
+
+    /// A searcher over all the segments.
+    pub fn searcher(&self) -> Searcher<'_> {
+        Searcher::with_segments(&self.segments)
+    }
+
+    pub(crate) fn into_parts(self) -> (Manifest, Vec<Index>) {
+        (self.manifest, self.segments)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Commit, SegmentInfo, MANIFEST_FILE};
+    use crate::index::IndexWriter;
+    use crate::merge::NoMergePolicy;
+    use crate::query::{term::TermQuery, Query};
+    use crate::schema::{Document, FieldEntry, Schema};
+    use crate::testing::TempDir;
+
+    fn schema() -> Schema {
+        Schema::new()
+            .with_field(FieldEntry::new("id").with_indexed(false).with_stored(true))
+            .with_field(FieldEntry::new("title").with_stored(true))
+            .with_unique_key("id")
+    }
+
+    fn document(id: &str, title: &str) -> Document {
+        Document::new()
+            .with_value("id", id)
+            .with_value("title", title)
+    }
+
+    #[test]
+    fn test_segment_info() {
+        let mut info = SegmentInfo::new(3);
+        assert_eq!(info.files(), vec!["_3.doc", "_3.post", "_3.store"]);
+        info.live_docs_generation = Some(7);
+        assert_eq!(info.live_docs_file().unwrap(), "_3_7.liv");
+    }
+
+    #[test]
+    fn test_commits() {
+        let directory = TempDir::new("commits");
+        let directory_path = directory.path();
+        assert!(Commit::open(directory_path).is_err());
+
+        let mut writer = IndexWriter::with_schema(directory_path, schema())
+            .unwrap()
+            .with_merge_policy(NoMergePolicy);
+        writer.add(&document("a", "rough collie")).unwrap();
+        writer.add(&document("b", "sheltie")).unwrap();
+        writer.commit().unwrap();
+        let first = Commit::open(directory_path).unwrap();
+        assert_eq!(first.generation(), 1);
+        assert_eq!(first.segments().len(), 1);
+
+        // Each commit adds a segment with the documents added since the previous one.
+        writer.add(&document("c", "smooth collie")).unwrap();
+        writer.add(&document("d", "corgi")).unwrap();
+        assert!(writer.add(&document("a", "duplicate")).is_err());
+        writer.commit().unwrap();
+        writer.commit().unwrap();
+        let commit = Commit::open(directory_path).unwrap();
+        assert_eq!(commit.generation(), 3);
+        assert_eq!(commit.segments().len(), 2);
+        assert_eq!(commit.doc_count(), 4);
+
+        // Doc ids are numbered across segments.
+        let searcher = commit.searcher();
+        let results = searcher.search("collie", 10);
+        let mut ids = results
+            .iter()
+            .map(|result| searcher.doc(result.doc_id).unwrap())
+            .map(|doc| doc.get_first("id").unwrap().to_string())
+            .collect::<Vec<String>>();
+        ids.sort();
+        assert_eq!(ids, vec!["a", "c"]);
+        assert_eq!(searcher.doc_id("d"), Some(4));
+        assert_eq!(searcher.doc(4).unwrap().get_first("title"), Some("corgi"));
+        assert_eq!(searcher.doc(0), None);
+        assert_eq!(searcher.doc(5), None);
+        assert_eq!(
+            searcher
+                .search_query(&TermQuery::new("corgi".to_string()), 10)
+                .iter()
+                .map(|result| result.doc_id)
+                .collect::<Vec<usize>>(),
+            vec![4]
+        );
+
+        // Deletions in committed segments are written to new live docs files, and the
+        // readers of previous commits keep their view.
+        let mut writer = IndexWriter::open(directory_path)
+            .unwrap()
+            .with_merge_policy(NoMergePolicy);
+        assert!(writer.delete_by_id("a"));
+        writer
+            .update_document("d", &document("d", "cardigan corgi"))
+            .unwrap();
+        writer.commit().unwrap();
+        let commit = Commit::open(directory_path).unwrap();
+        assert_eq!(commit.segments().len(), 3);
+        assert_eq!(commit.doc_count(), 3);
+        let searcher = commit.searcher();
+        assert_eq!(searcher.search("collie", 10).len(), 1);
+        assert_eq!(searcher.doc_id("d"), Some(5));
+        assert_eq!(first.searcher().search("collie", 10).len(), 1);
+        assert_eq!(first.segments()[0].doc_count(), 2);
+
+        // Segments whose documents are all deleted are dropped, and unused files removed,
+        // including the temporary files left behind by a crash.
+        std::fs::write(directory_path.join("_9.tmp"), b"").unwrap();
+        std::fs::write(directory_path.join("manifest.tmp"), b"").unwrap();
+        writer.delete_by_id("b");
+        writer.commit().unwrap();
+        let commit = Commit::open(directory_path).unwrap();
+        assert_eq!(commit.segments().len(), 2);
+        let mut files = std::fs::read_dir(directory_path)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<String>>();
+        files.sort();
+        let mut expected = commit
+            .manifest()
+            .segments()
+            .iter()
+            .flat_map(|info| info.files())
+            .collect::<Vec<String>>();
+        expected.push(MANIFEST_FILE.to_string());
+        expected.sort();
+        assert_eq!(files, expected);
+        assert_eq!(commit.segments()[0].doc(2), None);
+
+        // A new writer starts an empty index, without reusing segment names.
+        let mut writer = IndexWriter::with_schema(directory_path, schema()).unwrap();
+        writer.add(&document("e", "sheltie")).unwrap();
+        writer.commit().unwrap();
+        let commit = Commit::open(directory_path).unwrap();
+        assert_eq!(commit.doc_count(), 1);
+        assert_eq!(commit.manifest().segments()[0].name(), "_3");
+        assert_eq!(
+            TermQuery::new("sheltie".to_string())
+                .execute(&commit.segments()[0])
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_interrupted_commit() {
+        let directory = TempDir::new("interrupted");
+        let directory_path = directory.path();
+        let mut writer = IndexWriter::with_schema(directory_path, schema())
+            .unwrap()
+            .with_merge_policy(NoMergePolicy);
+        writer.add(&document("a", "rough collie")).unwrap();
+        writer.add(&document("b", "sheltie")).unwrap();
+        writer.commit().unwrap();
+        drop(writer);
+
+        // A writer that crashed while updating a document left the new segment and live
+        // docs files, but did not replace the manifest: readers see the previous commit,
+        // with the old version of the document only.
+        let files = std::fs::read_dir(directory_path)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>();
+        for (file, stray) in [
+            ("_0.doc", "_1.doc"),
+            ("_0.post", "_1.post"),
+            ("_0.store", "_1.store"),
+            ("_0.store", "_0_2.liv"),
+            ("manifest", "manifest.tmp"),
+        ] {
+            std::fs::copy(directory_path.join(file), directory_path.join(stray)).unwrap();
+        }
+        let commit = Commit::open(directory_path).unwrap();
+        assert_eq!(commit.generation(), 1);
+        assert_eq!(commit.doc_count(), 2);
+        assert_eq!(commit.searcher().doc_id("b"), Some(2));
+
+        // The next commit replaces the stray files.
+        let mut writer = IndexWriter::open(directory_path)
+            .unwrap()
+            .with_merge_policy(NoMergePolicy);
+        writer
+            .update_document("b", &document("b", "shetland sheepdog"))
+            .unwrap();
+        writer.commit().unwrap();
+        drop(writer);
+        let commit = Commit::open(directory_path).unwrap();
+        assert_eq!(commit.generation(), 2);
+        assert_eq!(commit.doc_count(), 2);
+        assert_eq!(commit.searcher().search("sheltie", 10).len(), 0);
+        assert_eq!(commit.searcher().search("sheepdog", 10).len(), 1);
+        let mut remaining = std::fs::read_dir(directory_path)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .filter(|file| !files.contains(file))
+            .collect::<Vec<_>>();
+        remaining.sort();
+        assert_eq!(remaining, ["_0_2.liv", "_1.doc", "_1.post", "_1.store"]);
+    }
+}
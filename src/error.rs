@@ -0,0 +1,90 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use crate::format::{FORMAT_VERSION, MIN_FORMAT_VERSION};
+
+/// An error reading an index from a directory.
+#[derive(Debug)]
+pub enum IndexError {
+    /// The directory holds no index.
+    NotFound(PathBuf),
+    /// A file of the index cannot be decoded, is missing, or does not match the others.
+    Corrupted { path: PathBuf, message: String },
+    /// The contents of a file do not match the checksum in its footer.
+    ChecksumMismatch {
+        path: PathBuf,
+        expected: u32, // checksum in the footer
+        actual: u32,   // checksum of the contents
+    },
+    /// A file was written with a format version that this version of the crate does not
+    /// read. See `format::FORMAT_VERSION`.
+    UnsupportedVersion { path: PathBuf, version: u32 },
+    /// A file of the index cannot be read.
+    Io { path: PathBuf, source: io::Error },
+}
+
+impl IndexError {
+    pub(crate) fn corrupted(path: PathBuf, message: impl Into<String>) -> Self {
+        IndexError::Corrupted {
+            path,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for IndexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IndexError::NotFound(path) => write!(f, "no index in {}", path.display()),
+            IndexError::Corrupted { path, message } => {
+                write!(f, "corrupted index file {}: {}", path.display(), message)
+            }
+            IndexError::ChecksumMismatch {
+                path,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "checksum mismatch in {}: expected {:08x}, found {:08x}",
+                path.display(),
+                expected,
+                actual
+            ),
+            IndexError::UnsupportedVersion { path, version } => write!(
+                f,
+                "unsupported format version {} of {}, expected {} to {}",
+                version,
+                path.display(),
+                MIN_FORMAT_VERSION,
+                FORMAT_VERSION
+            ),
+            IndexError::Io { path, source } => {
+                write!(f, "failed to read {}: {}", path.display(), source)
+            }
+        }
+    }
+}
+
+impl Error for IndexError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            IndexError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<IndexError> for io::Error {
+    fn from(err: IndexError) -> Self {
+        let kind = match &err {
+            IndexError::NotFound(_) => io::ErrorKind::NotFound,
+            IndexError::Corrupted { .. }
+            | IndexError::ChecksumMismatch { .. }
+            | IndexError::UnsupportedVersion { .. } => io::ErrorKind::InvalidData,
+            IndexError::Io { source, .. } => source.kind(),
+        };
+        io::Error::new(kind, err)
+    }
+}
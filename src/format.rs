@@ -0,0 +1,195 @@
+use std::convert::TryInto;
+use std::fmt;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::error::IndexError;
+
+/// Bytes starting every index file.
+pub const MAGIC: [u8; 4] = *b"SHLT";
+
+/// Version of the on-disk format written by this version of the crate.
+///
+/// Compatibility policy: every index file starts with a header holding `MAGIC`, the
+/// format version and the kind of the file, and ends with the CRC32 of all the bytes
+/// before it. Any change to the encoding of a file, including a change to a serialized
+/// struct such as `Index` or `PostingsList`, bumps the format version. Readers accept the
+/// versions from `MIN_FORMAT_VERSION` to `FORMAT_VERSION` and report any other version as
+/// `IndexError::UnsupportedVersion`, rather than misreading the file. Writers always
+/// write `FORMAT_VERSION`, including when they merge segments written with an older one,
+/// so merging upgrades an index. Dropping support for a version raises
+/// `MIN_FORMAT_VERSION`, after which indexes written with it have to be rebuilt. Files
+/// written before format versions existed have no header and are reported as corrupted.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Oldest format version that this version of the crate reads.
+pub const MIN_FORMAT_VERSION: u32 = 1;
+
+/// Length of the header of a file: the magic bytes, the format version and the kind.
+pub const HEADER_LEN: usize = 12;
+
+/// Length of the footer of a file: the CRC32 of the header and the body.
+pub const FOOTER_LEN: usize = 4;
+
+/// What an index file holds, recorded in its header so that a file cannot be mistaken
+/// for another one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileKind {
+    Manifest = 1,
+    Segment = 2,
+    Postings = 3,
+    StoredFields = 4,
+    LiveDocs = 5,
+}
+
+impl fmt::Display for FileKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            FileKind::Manifest => "manifest",
+            FileKind::Segment => "segment",
+            FileKind::Postings => "postings",
+            FileKind::StoredFields => "stored fields",
+            FileKind::LiveDocs => "live docs",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The header of a file of the given kind, written with the current format version.
+pub(crate) fn header(kind: FileKind) -> [u8; HEADER_LEN] {
+    let mut header = [0; HEADER_LEN];
+    header[..4].copy_from_slice(&MAGIC);
+    header[4..8].copy_from_slice(&FORMAT_VERSION.to_le_bytes());
+    header[8..].copy_from_slice(&(kind as u32).to_le_bytes());
+    header
+}
+
+/// Checks the header of the bytes of a file of the given kind, and returns the range of
+/// its body, between the header and the footer. The checksum is not verified.
+pub(crate) fn check_header(
+    path: &Path,
+    kind: FileKind,
+    data: &[u8],
+) -> Result<Range<usize>, IndexError> {
+    if data.len() < HEADER_LEN + FOOTER_LEN || data[..4] != MAGIC {
+        return Err(IndexError::corrupted(
+            path.to_path_buf(),
+            "missing header, not an index file",
+        ));
+    }
+    let version = read_u32(&data[4..8]);
+    if !(MIN_FORMAT_VERSION..=FORMAT_VERSION).contains(&version) {
+        return Err(IndexError::UnsupportedVersion {
+            path: path.to_path_buf(),
+            version,
+        });
+    }
+    if read_u32(&data[8..12]) != kind as u32 {
+        return Err(IndexError::corrupted(
+            path.to_path_buf(),
+            format!("not a {} file", kind),
+        ));
+    }
+    Ok(HEADER_LEN..data.len() - FOOTER_LEN)
+}
+
+/// Verifies the checksum in the footer of the bytes of a file.
+pub(crate) fn verify_checksum(path: &Path, data: &[u8]) -> Result<(), IndexError> {
+    let end = data.len().saturating_sub(FOOTER_LEN);
+    let expected = read_u32(&data[end..]);
+    let actual = crc32fast::hash(&data[..end]);
+    if data.len() < FOOTER_LEN || expected != actual {
+        return Err(IndexError::ChecksumMismatch {
+            path: path.to_path_buf(),
+            expected,
+            actual,
+        });
+    }
+    Ok(())
+}
+
+/// Checks the header and the checksum of the bytes of a file, and returns its body.
+pub(crate) fn check_file<'a>(
+    path: &Path,
+    kind: FileKind,
+    data: &'a [u8],
+) -> Result<&'a [u8], IndexError> {
+    let body = check_header(path, kind, data)?;
+    verify_checksum(path, data)?;
+    Ok(&data[body])
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    bytes.try_into().map_or(0, u32::from_le_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{check_file, header, FileKind, FORMAT_VERSION, HEADER_LEN};
+    use crate::error::IndexError;
+
+    fn file(kind: FileKind, body: &[u8]) -> Vec<u8> {
+        let mut data = header(kind).to_vec();
+        data.extend_from_slice(body);
+        let checksum = crc32fast::hash(&data);
+        data.extend_from_slice(&checksum.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn test_check_file() {
+        let path = Path::new("_0.doc");
+        let data = file(FileKind::Segment, b"sheltie");
+        assert_eq!(
+            check_file(path, FileKind::Segment, &data).unwrap(),
+            b"sheltie"
+        );
+        let empty = file(FileKind::LiveDocs, b"");
+        assert_eq!(check_file(path, FileKind::LiveDocs, &empty).unwrap(), b"");
+
+        // Files of another kind, and files without a header.
+        let err = check_file(path, FileKind::Postings, &data).unwrap_err();
+        assert!(matches!(err, IndexError::Corrupted { .. }), "{}", err);
+        let err = check_file(path, FileKind::Segment, b"sheltie").unwrap_err();
+        assert!(matches!(err, IndexError::Corrupted { .. }), "{}", err);
+        let err = check_file(path, FileKind::Segment, &data[..HEADER_LEN]).unwrap_err();
+        assert!(matches!(err, IndexError::Corrupted { .. }), "{}", err);
+
+        // Versions that are not supported are reported before the checksum is checked.
+        let mut newer = data.clone();
+        newer[4..8].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        let err = check_file(path, FileKind::Segment, &newer).unwrap_err();
+        assert!(
+            matches!(err, IndexError::UnsupportedVersion { version, .. } if version == FORMAT_VERSION + 1),
+            "{}",
+            err
+        );
+        let mut older = data.clone();
+        older[4..8].copy_from_slice(&0u32.to_le_bytes());
+        let err = check_file(path, FileKind::Segment, &older).unwrap_err();
+        assert!(matches!(
+            err,
+            IndexError::UnsupportedVersion { version: 0, .. }
+        ));
+
+        // Any modified or truncated byte after the header fails the checksum.
+        for i in HEADER_LEN..data.len() {
+            let mut corrupted = data.clone();
+            corrupted[i] ^= 0x10;
+            let err = check_file(path, FileKind::Segment, &corrupted).unwrap_err();
+            assert!(
+                matches!(err, IndexError::ChecksumMismatch { .. }),
+                "{}",
+                err
+            );
+        }
+        let err = check_file(path, FileKind::Segment, &data[..data.len() - 1]).unwrap_err();
+        assert!(
+            matches!(err, IndexError::ChecksumMismatch { .. }),
+            "{}",
+            err
+        );
+    }
+}
@@ -1,134 +1,2201 @@
-use std::collections::HashMap;
-use std::fs::File;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fs::{self, File};
 use std::io;
 use std::io::prelude::*;
-use std::path::Path;
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
 
 use bincode;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
-use crate::analyzer::analyze;
+use crate::analyzer::{Analyzer, AnalyzerConfig, TextAnalyzer};
+use crate::bytes::Bytes;
+use crate::codec::{decode_vbyte, encode_vbyte};
+use crate::commit::{
+    Commit, Manifest, SegmentInfo, LIVE_DOCS_EXTENSION, MANIFEST_FILE, POSTINGS_EXTENSION,
+    SEGMENT_EXTENSION, STORED_FIELDS_EXTENSION, TEMP_EXTENSION,
+};
+use crate::error::IndexError;
+use crate::format::{self, FileKind};
+use crate::live_docs::LiveDocs;
+use crate::merge::{MergePolicy, SegmentSize, TieredMergePolicy};
+use crate::schema::{Document, FieldEntry, Schema, DEFAULT_FIELD};
+use crate::similarity::{Similarity, TermStats};
+use crate::store::{read_stored_fields, StoredFields};
+use crate::terms::automaton::AlwaysMatch;
+use crate::terms::{self, encode_postings, read_postings, Automaton, TermDictionary};
 
+/// A segment of an index: an inverted index per indexed field of a schema, the stored
+/// fields of the documents and which of them have been deleted. The postings lists, the
+/// stored fields and the live docs are written separately, to the files named by the
+/// `SegmentInfo` of the segment.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Index {
-    inverted_index: HashMap<String, PostingsList>,
+    schema: Schema,
+    fields: HashMap<String, FieldIndex>,
     max_doc_id: usize,
+    ids: HashMap<String, usize>, // doc_id of each external id
+    keys: Vec<Option<String>>,   // external id of each document, indexed by doc_id - 1
+    #[serde(skip)]
+    stored: StoredFields,
+    #[serde(skip)]
+    live_docs: LiveDocs,
+    #[serde(skip)]
+    mapped_files: Vec<(PathBuf, Bytes)>, // postings and stored fields files, once written
 }
 
 impl Index {
     pub fn new() -> Self {
+        Self::with_analyzer(AnalyzerConfig::default())
+    }
+
+    /// Creates an index with a single field, `DEFAULT_FIELD`, analyzed as described by
+    /// `config`.
+    pub fn with_analyzer(config: AnalyzerConfig) -> Self {
+        Self::with_schema(
+            Schema::new().with_field(FieldEntry::new(DEFAULT_FIELD).with_analyzer(config)),
+        )
+    }
+
+    pub fn with_schema(schema: Schema) -> Self {
+        let fields = schema
+            .fields()
+            .iter()
+            .filter(|field| field.is_indexed())
+            .map(|field| (field.name().to_string(), FieldIndex::new()))
+            .collect();
         Index {
-            inverted_index: HashMap::new(),
+            schema,
+            fields,
             max_doc_id: 0,
+            ids: HashMap::new(),
+            keys: vec![],
+            stored: StoredFields::new(),
+            live_docs: LiveDocs::new(),
+            mapped_files: vec![],
         }
     }
 
-    pub fn add(&mut self, text: &String) {
-        let tokens = analyze(&text);
-        let freq_map = {
-            let mut freq_map = HashMap::<String, u32>::new();
-            for token in tokens {
-                let freq = freq_map.entry(token.token).or_insert(0);
-                *freq += 1;
+    /// Reads the segment described by `info` from `directory_path`. The postings and
+    /// stored fields files are memory-mapped rather than read, so that only the terms
+    /// that are searched and the documents that are fetched are loaded. The segment file,
+    /// with the lengths and the unique keys of the documents, is read into memory.
+    /// Fails with `IndexError::Corrupted` if its files do not hold the same documents.
+    pub(crate) fn read_segment(
+        directory_path: &Path,
+        info: &SegmentInfo,
+    ) -> Result<Self, IndexError> {
+        let segment_path = directory_path.join(info.segment_file());
+        let mut index: Index = read_file(&segment_path, FileKind::Segment)?;
+        let max_doc_id = index.max_doc_id;
+        if index.keys.len() != max_doc_id
+            || index
+                .fields
+                .values()
+                .any(|field| field.doc_lengths.len() > max_doc_id)
+        {
+            return Err(IndexError::corrupted(
+                segment_path,
+                "inconsistent number of documents",
+            ));
+        }
+        let postings_path = directory_path.join(info.postings_file());
+        let (postings_data, mut dictionaries) = read_postings(&postings_path)?;
+        for (name, field) in index.fields.iter_mut() {
+            let dictionary = dictionaries.remove(name).ok_or_else(|| {
+                IndexError::corrupted(postings_path.clone(), format!("no terms for {}", name))
+            })?;
+            field.terms = Terms::Mapped(dictionary);
+        }
+        let stored_fields_path = directory_path.join(info.stored_fields_file());
+        let (stored_fields_data, stored) = read_stored_fields(&stored_fields_path)?;
+        index.stored = stored;
+        if index.stored.len() != max_doc_id {
+            return Err(IndexError::corrupted(
+                stored_fields_path,
+                format!("{} documents instead of {}", index.stored.len(), max_doc_id),
+            ));
+        }
+        index.mapped_files = vec![
+            (postings_path, postings_data),
+            (stored_fields_path, stored_fields_data),
+        ];
+        index.live_docs = match info.live_docs_file() {
+            Some(file) => {
+                let live_docs_path = directory_path.join(file);
+                let live_docs: LiveDocs = read_file(&live_docs_path, FileKind::LiveDocs)?;
+                if live_docs.max_doc_id() != max_doc_id {
+                    return Err(IndexError::corrupted(
+                        live_docs_path,
+                        format!(
+                            "{} documents instead of {}",
+                            live_docs.max_doc_id(),
+                            max_doc_id
+                        ),
+                    ));
+                }
+                live_docs
             }
-            freq_map
+            None => LiveDocs::with_max_doc_id(max_doc_id),
         };
+        // The segment file keeps the unique keys of the documents deleted after it was
+        // written.
+        for doc_id in 1..=index.max_doc_id {
+            if !index.is_live(doc_id) {
+                if let Some(key) = index.keys[doc_id - 1].take() {
+                    index.ids.remove(&key);
+                }
+            }
+        }
+        Ok(index)
+    }
 
+    /// Verifies the checksums of the postings and stored fields files of a segment that
+    /// was read. The other files are verified when they are read, but these ones are only
+    /// mapped, and verifying them reads all of them. Segments are verified before being
+    /// merged.
+    pub fn check_integrity(&self) -> Result<(), IndexError> {
+        self.mapped_files
+            .iter()
+            .try_for_each(|(path, data)| format::verify_checksum(path, data))
+    }
+
+    /// Writes the segment described by `info` to `directory_path`.
+    fn write_segment(&self, directory_path: &Path, info: &SegmentInfo) -> io::Result<()> {
+        write_file(
+            &directory_path.join(info.segment_file()),
+            FileKind::Segment,
+            self,
+        )?;
+        write_bytes(
+            &directory_path.join(info.postings_file()),
+            FileKind::Postings,
+            &encode_postings(&self.fields)?,
+        )?;
+        write_bytes(
+            &directory_path.join(info.stored_fields_file()),
+            FileKind::StoredFields,
+            &self.stored.encode(),
+        )?;
+        self.write_live_docs(directory_path, info)
+    }
+
+    /// Writes the live docs of the segment described by `info`, if it has deletions.
+    fn write_live_docs(&self, directory_path: &Path, info: &SegmentInfo) -> io::Result<()> {
+        match info.live_docs_file() {
+            Some(file) => write_file(
+                &directory_path.join(file),
+                FileKind::LiveDocs,
+                &self.live_docs,
+            ),
+            None => Ok(()),
+        }
+    }
+
+    /// Adds a document whose default field holds `text`, and returns its doc_id.
+    ///
+    /// Fails with `InvalidInput` if the schema has a unique key, as the document would not
+    /// have a value for it. Use `add_document` instead.
+    pub fn add(&mut self, text: &str) -> io::Result<usize> {
+        let mut document = Document::new();
+        if let Some(field) = self.schema.default_field() {
+            document.add_value(field.name(), text);
+        }
+        self.add_document(&document)
+    }
+
+    /// Adds a document and returns its doc_id. Doc ids are assigned in increasing order,
+    /// starting from 1. The values of each indexed field are analyzed with the analyzer of
+    /// the field, and the values of the stored fields are kept. The values of the fields
+    /// that are not in the schema are ignored.
+    ///
+    /// Fails with `InvalidInput` if the schema has a unique key and the document does not
+    /// have exactly one value for it, or another document already has that value.
+    pub fn add_document(&mut self, document: &Document) -> io::Result<usize> {
+        let key = match self.schema.unique_key() {
+            Some(unique_key) => {
+                let mut values = document.get(unique_key);
+                let key = match (values.next(), values.next()) {
+                    (Some(key), None) => key,
+                    _ => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("a document must have one value for {:?}", unique_key),
+                        ))
+                    }
+                };
+                if self.ids.contains_key(key) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("duplicate {:?}: {:?}", unique_key, key),
+                    ));
+                }
+                Some(key.to_string())
+            }
+            None => None,
+        };
+
+        for field in self.fields.values_mut() {
+            field.load_terms()?;
+        }
         let doc_id = self.max_doc_id + 1;
-        for (token, freq) in &freq_map {
-            if let Some(postings_list) = self.inverted_index.get_mut(token) {
-                postings_list.add(doc_id, *freq);
-            } else {
-                let mut posting_list = PostingsList::new();
-                posting_list.add(doc_id, *freq);
-                self.inverted_index.insert(token.clone(), posting_list);
+        for (name, field) in self.fields.iter_mut() {
+            let analyzer = match self.schema.field(name) {
+                Some(entry) => entry.analyzer(),
+                None => continue,
+            };
+            field.add(doc_id, analyzer, document.get(name));
+        }
+        let mut stored = Document::new();
+        for (name, value) in document.values() {
+            if self
+                .schema
+                .field(name)
+                .is_some_and(|entry| entry.is_stored())
+            {
+                stored.add_value(name, value);
             }
         }
+        self.stored.add(stored);
+        if let Some(key) = &key {
+            self.ids.insert(key.clone(), doc_id);
+        }
+        self.keys.push(key);
+        self.live_docs.push();
         self.max_doc_id = doc_id;
+        Ok(doc_id)
+    }
+
+    /// Replaces the document whose unique key is `key` with `document`, and returns the
+    /// doc_id of the new version. The old version is deleted and the new one added in a
+    /// single operation, so that they are never both visible, nor both missing. If there
+    /// is no document with that key, `document` is simply added.
+    ///
+    /// Fails with `InvalidInput`, without changing the index, if the schema has no unique
+    /// key, or `document` does not have `key` as its only value for it.
+    pub fn update_document(&mut self, key: &str, document: &Document) -> io::Result<usize> {
+        let unique_key = self.schema.unique_key().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "documents can only be updated in an index with a unique key",
+            )
+        })?;
+        if !document.get(unique_key).eq([key]) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "the document must have {:?} as its only {:?}",
+                    key, unique_key
+                ),
+            ));
+        }
+        self.delete_by_id(key);
+        self.add_document(document)
+    }
+
+    /// Deletes a document. Its postings are kept, but it no longer matches any query, and
+    /// its unique key can be given to another document. Returns `false` if there is no
+    /// such live document.
+    pub fn delete_document(&mut self, doc_id: usize) -> bool {
+        if !self.live_docs.delete(doc_id) {
+            return false;
+        }
+        if let Some(key) = self.keys.get_mut(doc_id - 1).and_then(Option::take) {
+            self.ids.remove(&key);
+        }
+        true
+    }
+
+    /// Deletes the document whose unique key is `id`. Returns `false` if there is none.
+    pub fn delete_by_id(&mut self, id: &str) -> bool {
+        match self.doc_id(id) {
+            Some(doc_id) => self.delete_document(doc_id),
+            None => false,
+        }
+    }
+
+    /// Deletes the documents containing `term` in `field`. The term is looked up as it
+    /// is, without analysis. Returns the number of deleted documents.
+    ///
+    /// Fails with `InvalidData`, without deleting any document, if the postings list of
+    /// the term is corrupted.
+    pub fn delete_by_term(&mut self, field: &str, term: &str) -> io::Result<usize> {
+        let doc_ids = match self
+            .fields
+            .get(field)
+            .and_then(|field| field.get_postings_list(term))
+        {
+            Some(postings_list) => postings_list
+                .doc_ids()
+                .ok_or_else(|| corrupted_postings(term))?,
+            None => return Ok(0),
+        };
+        Ok(doc_ids
+            .into_iter()
+            .filter(|&doc_id| self.delete_document(doc_id))
+            .count())
+    }
+
+    /// Whether a document exists and has not been deleted.
+    pub fn is_live(&self, doc_id: usize) -> bool {
+        self.live_docs.is_live(doc_id)
+    }
+
+    /// The doc_id of the live document whose unique key is `id`.
+    pub fn doc_id(&self, id: &str) -> Option<usize> {
+        self.ids.get(id).copied()
+    }
+
+    /// The unique key of a live document.
+    pub fn external_id(&self, doc_id: usize) -> Option<&str> {
+        self.keys.get(doc_id.checked_sub(1)?)?.as_deref()
+    }
+
+    /// The values of the stored fields of a live document.
+    pub fn doc(&self, doc_id: usize) -> Option<Document> {
+        if !self.is_live(doc_id) {
+            return None;
+        }
+        self.stored.get(doc_id)
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// The inverted indexes of the indexed fields, by name.
+    pub fn fields(&self) -> &HashMap<String, FieldIndex> {
+        &self.fields
+    }
+
+    /// The inverted index of an indexed field.
+    pub fn field(&self, name: &str) -> Option<&FieldIndex> {
+        self.fields.get(name)
+    }
+
+    /// The schema entry and the inverted index of the field `name`, or of the default
+    /// field if `name` is `None`. Returns `None` if the field is not indexed.
+    pub fn resolve_field(&self, name: Option<&str>) -> Option<(&FieldEntry, &FieldIndex)> {
+        let entry = match name {
+            Some(name) => self.schema.field(name)?,
+            None => self.schema.default_field()?,
+        };
+        Some((entry, self.fields.get(entry.name())?))
+    }
+
+    /// Merges segments into one, in which the live documents keep their order and the
+    /// deleted ones are dropped. Also returns, for each segment, the new doc_id of each of
+    /// its documents, indexed by doc_id - 1, or `None` if it was deleted. The merged
+    /// segment has the schema of the last segment.
+    ///
+    /// Fails with `InvalidData` if a postings list of a segment is corrupted.
+    pub fn merge(segments: &[Index]) -> io::Result<(Index, Vec<Vec<Option<usize>>>)> {
+        let schema = segments
+            .last()
+            .map_or_else(Schema::new, |segment| segment.schema.clone());
+        let mut merged = Index::with_schema(schema);
+        let mut doc_maps = Vec::with_capacity(segments.len());
+        for segment in segments {
+            let mut doc_map = Vec::with_capacity(segment.max_doc_id);
+            for doc_id in 1..=segment.max_doc_id {
+                if !segment.is_live(doc_id) {
+                    doc_map.push(None);
+                    continue;
+                }
+                let new_doc_id = merged.max_doc_id + 1;
+                merged
+                    .stored
+                    .add(segment.stored.get(doc_id).unwrap_or_default());
+                let key = segment.keys[doc_id - 1].clone();
+                if let Some(key) = &key {
+                    merged.ids.insert(key.clone(), new_doc_id);
+                }
+                merged.keys.push(key);
+                merged.live_docs.push();
+                merged.max_doc_id = new_doc_id;
+                doc_map.push(Some(new_doc_id));
+            }
+            doc_maps.push(doc_map);
+        }
+        for (name, field) in merged.fields.iter_mut() {
+            let sources = segments
+                .iter()
+                .zip(&doc_maps)
+                .filter_map(|(segment, doc_map)| Some((segment.fields.get(name)?, doc_map)))
+                .collect::<Vec<(&FieldIndex, &Vec<Option<usize>>)>>();
+            *field = FieldIndex::merge(&sources, merged.max_doc_id)?;
+        }
+        Ok((merged, doc_maps))
+    }
+
+    /// Number of live documents.
+    pub fn doc_count(&self) -> usize {
+        self.max_doc_id - self.live_docs.deleted()
+    }
+
+    /// The largest doc_id assigned so far, including deleted documents.
+    pub fn max_doc_id(&self) -> usize {
+        self.max_doc_id
+    }
+}
+
+impl Default for Index {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The inverted index of a field, with the statistics needed to score its terms.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct FieldIndex {
+    #[serde(skip)]
+    terms: Terms, // written to the postings file of the segment
+    doc_lengths: Vec<u32>, // number of tokens of the field in each document, indexed by doc_id - 1
+    total_length: u64,     // sum of all document lengths
+}
+
+impl FieldIndex {
+    pub fn new() -> Self {
+        FieldIndex::default()
+    }
+
+    /// Indexes the values of the field in a document. Each value starts one position after
+    /// the end of the previous one, so that phrases do not match across values.
+    fn add<'a>(
+        &mut self,
+        doc_id: usize,
+        analyzer: &TextAnalyzer,
+        values: impl Iterator<Item = &'a str>,
+    ) {
+        let mut positions_map = HashMap::<String, Vec<u32>>::new();
+        // Tokens stacked at the same position, such as synonyms, are counted once.
+        let mut distinct_positions = HashSet::new();
+        let mut offset = 0;
+        for value in values {
+            let tokens = analyzer.analyze(value);
+            let mut end = offset;
+            for token in tokens {
+                let position = offset + token.position;
+                end = end.max(position + token.position_length.max(1) + 1);
+                distinct_positions.insert(position);
+                positions_map.entry(token.token).or_default().push(position);
+            }
+            offset = end;
+        }
+        let doc_length = distinct_positions.len() as u32;
+
+        for (token, mut positions) in positions_map {
+            // Stacked alternatives may list a token out of order, or twice.
+            positions.sort_unstable();
+            positions.dedup();
+            self.inverted_index()
+                .entry(token)
+                .or_default()
+                .add(doc_id, positions, doc_length);
+        }
+        self.doc_lengths.resize(doc_id - 1, 0);
+        self.doc_lengths.push(doc_length);
+        self.total_length += doc_length as u64;
+    }
+
+    /// Merges the inverted indexes of a field in several segments, given the new doc_ids
+    /// of their documents. See `Index::merge`.
+    fn merge(
+        sources: &[(&FieldIndex, &Vec<Option<usize>>)],
+        max_doc_id: usize,
+    ) -> io::Result<Self> {
+        let mut merged = FieldIndex::new();
+        merged.doc_lengths.resize(max_doc_id, 0);
+        for (field, doc_map) in sources {
+            for (i, new_doc_id) in doc_map.iter().enumerate() {
+                if let Some(new_doc_id) = new_doc_id {
+                    let doc_length = field.doc_length(i + 1);
+                    merged.doc_lengths[new_doc_id - 1] = doc_length;
+                    merged.total_length += doc_length as u64;
+                }
+            }
+        }
+
+        let terms = sources
+            .iter()
+            .flat_map(|(field, _)| field.terms())
+            .collect::<Result<BTreeSet<String>, IndexError>>()?;
+        let (mut docs, mut freqs) = (vec![], vec![]);
+        for term in terms {
+            let mut postings_list = PostingsList::new();
+            for (field, doc_map) in sources {
+                let source = match field.get_postings_list(&term) {
+                    Some(source) => source,
+                    None => continue,
+                };
+                for block in 0..source.blocks.len() {
+                    source
+                        .decode_block(block, &mut docs, &mut freqs)
+                        .ok_or_else(|| corrupted_postings(&term))?;
+                    let positions = source
+                        .decode_block_positions(block, &freqs)
+                        .ok_or_else(|| corrupted_postings(&term))?;
+                    for (&doc_id, positions) in docs.iter().zip(positions) {
+                        let new_doc_id = doc_map
+                            .get(doc_id - 1)
+                            .ok_or_else(|| corrupted_postings(&term))?;
+                        if let Some(new_doc_id) = *new_doc_id {
+                            let doc_length = field.doc_length(doc_id);
+                            postings_list.add(new_doc_id, positions, doc_length);
+                        }
+                    }
+                }
+            }
+            if !postings_list.is_empty() {
+                merged.inverted_index().insert(term, postings_list);
+            }
+        }
+        Ok(merged)
+    }
+
+    /// Copies the postings lists of a segment that was read into memory, for the rare case
+    /// of adding documents to it.
+    fn load_terms(&mut self) -> io::Result<()> {
+        if let Terms::Mapped(dictionary) = &self.terms {
+            let inverted_index = dictionary
+                .terms()
+                .map(|term| {
+                    let term = term?;
+                    let postings_list = dictionary
+                        .get(&term)
+                        .ok_or_else(|| corrupted_postings(&term))?;
+                    Ok((term, postings_list))
+                })
+                .collect::<io::Result<_>>()?;
+            self.terms = Terms::Memory(inverted_index);
+        }
+        Ok(())
+    }
+
+    /// The in-memory postings lists. See `load_terms`.
+    fn inverted_index(&mut self) -> &mut BTreeMap<String, PostingsList> {
+        match &mut self.terms {
+            Terms::Memory(inverted_index) => inverted_index,
+            Terms::Mapped(_) => unreachable!("terms are loaded before they are modified"),
+        }
     }
 
-    pub fn get_postings_list(&self, term: &String) -> Option<&PostingsList> {
-        self.inverted_index.get(term)
+    /// The postings list of a term. The postings lists of a segment that was read are
+    /// mapped from its postings file, and decoded as they are iterated.
+    pub fn get_postings_list(&self, term: &str) -> Option<Cow<'_, PostingsList>> {
+        match &self.terms {
+            Terms::Memory(inverted_index) => inverted_index.get(term).map(Cow::Borrowed),
+            Terms::Mapped(dictionary) => dictionary.get(term).map(Cow::Owned),
+        }
+    }
+
+    /// The terms of the field, in increasing order. Terms of a segment that was read fail
+    /// with `IndexError::Corrupted` if they are not valid UTF-8.
+    pub fn terms(&self) -> impl Iterator<Item = Result<String, IndexError>> + '_ {
+        self.search_terms(AlwaysMatch)
+    }
+
+    /// The terms of the field accepted by `automaton`, in increasing order. Mapped term
+    /// dictionaries only visit the terms whose prefixes the automaton can still accept,
+    /// which makes prefix, wildcard, regular expression and fuzzy matching efficient.
+    pub fn search_terms<'a, A: Automaton + 'a>(
+        &'a self,
+        automaton: A,
+    ) -> impl Iterator<Item = Result<String, IndexError>> + 'a {
+        let terms: Box<dyn Iterator<Item = Result<String, IndexError>>> = match &self.terms {
+            Terms::Memory(inverted_index) => Box::new(
+                inverted_index
+                    .keys()
+                    .filter(move |term| terms::is_match(&automaton, term))
+                    .cloned()
+                    .map(Ok),
+            ),
+            Terms::Mapped(dictionary) => Box::new(dictionary.search(automaton)),
+        };
+        terms
+    }
+
+    /// The terms of the field between `start` and `end`, in increasing order.
+    pub fn terms_in_range<'a>(
+        &'a self,
+        start: Bound<&str>,
+        end: Bound<&str>,
+    ) -> impl Iterator<Item = Result<String, IndexError>> + 'a {
+        let is_empty = match (start, end) {
+            (Bound::Included(start), Bound::Included(end)) => start > end,
+            (
+                Bound::Included(start) | Bound::Excluded(start),
+                Bound::Included(end) | Bound::Excluded(end),
+            ) => start >= end,
+            _ => false,
+        };
+        let terms: Box<dyn Iterator<Item = Result<String, IndexError>>> = match &self.terms {
+            // `BTreeMap::range` panics on empty ranges.
+            Terms::Memory(_) if is_empty => Box::new(std::iter::empty()),
+            Terms::Memory(inverted_index) => Box::new(
+                inverted_index
+                    .range::<str, _>((start, end))
+                    .map(|(term, _)| Ok(term.clone())),
+            ),
+            Terms::Mapped(dictionary) => Box::new(dictionary.range(start, end)),
+        };
+        terms
+    }
+
+    pub fn doc_count(&self) -> usize {
+        self.doc_lengths.len()
+    }
+
+    pub fn doc_length(&self, doc_id: usize) -> u32 {
+        doc_id
+            .checked_sub(1)
+            .and_then(|i| self.doc_lengths.get(i))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    pub fn total_length(&self) -> u64 {
+        self.total_length
+    }
+
+    /// Number of documents containing the term.
+    pub fn doc_freq(&self, term: &str) -> usize {
+        self.get_postings_list(term)
+            .map_or(0, |postings_list| postings_list.len())
+    }
+
+    /// Number of occurrences of the term in all documents.
+    pub fn collection_freq(&self, term: &str) -> u64 {
+        self.get_postings_list(term)
+            .map_or(0, |postings_list| postings_list.collection_freq())
+    }
+
+    pub fn avg_doc_length(&self) -> f64 {
+        if self.doc_lengths.is_empty() {
+            0.0
+        } else {
+            self.total_length as f64 / self.doc_lengths.len() as f64
+        }
+    }
+}
+
+/// The postings lists of the terms of a field, sorted by term: in memory while its segment
+/// is built, and mapped from the term dictionary of the postings file of the segment once
+/// it is written.
+#[derive(Debug)]
+enum Terms {
+    Memory(BTreeMap<String, PostingsList>),
+    Mapped(TermDictionary),
+}
+
+impl Default for Terms {
+    fn default() -> Self {
+        Terms::Memory(BTreeMap::new())
     }
 }
 
+/// Adds, updates and deletes the documents of the index in a directory. Changes are
+/// buffered in memory until `commit`, which writes the added documents to a new segment
+/// and publishes it, together with the deletions, in a new manifest. Segments already
+/// committed are never rewritten, so that documents can be added continuously without
+/// reindexing the others.
+///
+/// After each commit, the merge policy of the writer picks segments to merge, which are
+/// merged in background threads. A finished merge replaces its segments at the next
+/// commit.
 pub struct IndexWriter<'a> {
-    index: Index,
     directory_path: &'a Path,
+    manifest: Manifest,           // the last commit, and the merges finished since
+    segments: Vec<WriterSegment>, // the segments of the manifest, in its order
+    buffer: Index,                // the documents added since the last commit
+    merge_policy: Box<dyn MergePolicy>,
+    merges: Vec<PendingMerge>,
+}
+
+/// A committed segment, and whether documents were deleted from it since the last commit.
+struct WriterSegment {
+    index: Index,
+    deleted: bool,
+}
+
+/// The merged segment and the new doc_ids of the documents of each merged segment, see
+/// `Index::merge`.
+type MergeOutput = (Index, Vec<Vec<Option<usize>>>);
+
+/// A merge running in a background thread. The files of its segments are kept until it
+/// finishes.
+struct PendingMerge {
+    sources: Vec<SegmentInfo>, // the merged segments, as of the commit the merge started at
+    target: SegmentInfo,
+    handle: JoinHandle<io::Result<MergeOutput>>,
 }
 
 impl<'a> IndexWriter<'a> {
-    pub fn new(path: &'a Path) -> Self {
-        IndexWriter {
-            index: Index::new(),
+    pub fn new(path: &'a Path) -> io::Result<Self> {
+        Self::create(path, Index::new())
+    }
+
+    /// Creates a writer for an index with a single field whose values and queries are
+    /// analyzed as described by `config`.
+    pub fn with_analyzer(path: &'a Path, config: AnalyzerConfig) -> io::Result<Self> {
+        Self::create(path, Index::with_analyzer(config))
+    }
+
+    /// Creates a writer for an index of documents with the fields of `schema`.
+    pub fn with_schema(path: &'a Path, schema: Schema) -> io::Result<Self> {
+        Self::create(path, Index::with_schema(schema))
+    }
+
+    /// Creates a writer for an empty index. The first commit replaces the index previously
+    /// in the directory, if any, whose segment names are not reused.
+    ///
+    /// Fails if the directory holds an index whose manifest cannot be read, as its segment
+    /// names would otherwise be reused.
+    fn create(path: &'a Path, buffer: Index) -> io::Result<Self> {
+        let mut manifest = match Manifest::open(path) {
+            Ok(manifest) => manifest,
+            Err(IndexError::NotFound(_)) => Manifest::default(),
+            Err(err) => return Err(err.into()),
+        };
+        manifest.segments.clear();
+        Ok(IndexWriter {
+            directory_path: path,
+            manifest,
+            segments: vec![],
+            buffer,
+            merge_policy: Box::new(TieredMergePolicy::default()),
+            merges: vec![],
+        })
+    }
+
+    /// Creates a writer adding to, updating and deleting the documents of the last commit
+    /// of the index in `path`.
+    pub fn open(path: &'a Path) -> io::Result<Self> {
+        let (manifest, segments) = Commit::open(path)?.into_parts();
+        let schema = match segments.last() {
+            Some(segment) => segment.schema().clone(),
+            None => Schema::new(),
+        };
+        Ok(IndexWriter {
             directory_path: path,
+            manifest,
+            segments: segments
+                .into_iter()
+                .map(|index| WriterSegment {
+                    index,
+                    deleted: false,
+                })
+                .collect(),
+            buffer: Index::with_schema(schema),
+            merge_policy: Box::new(TieredMergePolicy::default()),
+            merges: vec![],
+        })
+    }
+
+    /// Sets the policy picking the segments to merge. Defaults to `TieredMergePolicy`.
+    pub fn with_merge_policy<P: MergePolicy + 'static>(mut self, merge_policy: P) -> Self {
+        self.merge_policy = Box::new(merge_policy);
+        self
+    }
+
+    /// Adds a document, and returns its doc_id in the segment of the next commit. See
+    /// `Index::add_document`.
+    ///
+    /// Also fails with `InvalidInput` if a committed document has the same unique key.
+    pub fn add(&mut self, document: &Document) -> io::Result<usize> {
+        if let Some(unique_key) = self.buffer.schema().unique_key() {
+            if let Some(key) = document.get_first(unique_key) {
+                if self
+                    .segments
+                    .iter()
+                    .any(|segment| segment.index.doc_id(key).is_some())
+                {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("duplicate {:?}: {:?}", unique_key, key),
+                    ));
+                }
+            }
+        }
+        self.buffer.add_document(document)
+    }
+
+    /// Replaces the document whose unique key is `key`, committed or not, and returns the
+    /// doc_id of the new version in the segment of the next commit. See
+    /// `Index::update_document`. Readers see the change once it is committed.
+    pub fn update_document(&mut self, key: &str, document: &Document) -> io::Result<usize> {
+        let doc_id = self.buffer.update_document(key, document)?;
+        for segment in self.segments.iter_mut() {
+            segment.deleted |= segment.index.delete_by_id(key);
+        }
+        Ok(doc_id)
+    }
+
+    /// Deletes the document whose unique key is `id`. See `Index::delete_by_id`.
+    pub fn delete_by_id(&mut self, id: &str) -> bool {
+        let mut deleted = self.buffer.delete_by_id(id);
+        for segment in self.segments.iter_mut() {
+            if segment.index.delete_by_id(id) {
+                segment.deleted = true;
+                deleted = true;
+            }
         }
+        deleted
     }
 
-    pub fn add(&mut self, text: &String) {
-        self.index.add(text);
+    /// Deletes the documents containing `term` in `field`. See `Index::delete_by_term`.
+    ///
+    /// Fails if the postings list of the term is corrupted in a segment, after deleting
+    /// the documents of the segments before it.
+    pub fn delete_by_term(&mut self, field: &str, term: &str) -> io::Result<usize> {
+        let mut count = self.buffer.delete_by_term(field, term)?;
+        for segment in self.segments.iter_mut() {
+            let deleted = segment.index.delete_by_term(field, term)?;
+            segment.deleted |= deleted > 0;
+            count += deleted;
+        }
+        Ok(count)
     }
 
-    pub fn export_index(&self) -> io::Result<()> {
-        let encoded: Vec<u8> = bincode::serialize(&self.index).unwrap();
-        let mut file = File::create(self.directory_path.join("segment.doc"))?;
-        file.write_all(&encoded)?;
-        file.flush()?;
+    /// Commits the changes: writes the documents added since the last commit to a new
+    /// segment, the new deletions of the committed segments to new live docs files, and
+    /// then the list of segments to `MANIFEST_FILE`, which is replaced atomically. Readers
+    /// see either all the changes since the previous commit, or none of them. The
+    /// directory is synced before the manifest is replaced, so that the manifest never
+    /// refers to files that a crash could lose, and again after, so that the commit is
+    /// durable once this returns.
+    ///
+    /// The merges finished since the last commit replace their segments, and new merges
+    /// are started afterwards. Segments whose documents are all deleted are dropped, and
+    /// the files that are no longer part of the index are removed.
+    ///
+    /// Fails without committing if a merge failed. The merge is then abandoned, and its
+    /// segments are left as they are.
+    pub fn commit(&mut self) -> io::Result<()> {
+        self.finish_merges(false)?;
+        let directory_path = self.directory_path;
+        let mut manifest = Manifest {
+            generation: self.manifest.generation + 1,
+            next_segment: self.manifest.next_segment,
+            segments: vec![],
+        };
+        for (info, segment) in self.manifest.segments.iter().zip(&self.segments) {
+            if segment.index.doc_count() == 0 {
+                continue;
+            }
+            let mut info = info.clone();
+            if segment.deleted {
+                info.live_docs_generation = Some(manifest.generation);
+                segment.index.write_live_docs(directory_path, &info)?;
+            }
+            manifest.segments.push(info);
+        }
+        let flush = self.buffer.doc_count() > 0;
+        if flush {
+            let mut info = SegmentInfo::new(manifest.next_segment);
+            manifest.next_segment += 1;
+            if self.buffer.max_doc_id() > self.buffer.doc_count() {
+                info.live_docs_generation = Some(manifest.generation);
+            }
+            self.buffer.write_segment(directory_path, &info)?;
+            manifest.segments.push(info);
+        }
+        sync_directory(directory_path)?;
+        write_file(
+            &directory_path.join(MANIFEST_FILE),
+            FileKind::Manifest,
+            &manifest,
+        )?;
+        sync_directory(directory_path)?;
+
+        self.segments
+            .retain(|segment| segment.index.doc_count() > 0);
+        for segment in self.segments.iter_mut() {
+            segment.deleted = false;
+        }
+        let buffer = Index::with_schema(self.buffer.schema().clone());
+        let buffer = std::mem::replace(&mut self.buffer, buffer);
+        if flush {
+            self.segments.push(WriterSegment {
+                index: buffer,
+                deleted: false,
+            });
+        }
+        self.manifest = manifest;
+        self.remove_unused_files()?;
+        self.start_merges();
+        Ok(())
+    }
+
+    /// Waits for the running merges to finish. They replace their segments at the next
+    /// commit. Fails if a merge failed.
+    pub fn wait_for_merges(&mut self) -> io::Result<()> {
+        self.finish_merges(true)
+    }
+
+    /// Starts the merges picked by the merge policy among the committed segments that are
+    /// not already being merged.
+    fn start_merges(&mut self) {
+        let merging = self
+            .merges
+            .iter()
+            .flat_map(|merge| &merge.sources)
+            .map(|info| info.name.as_str())
+            .collect::<HashSet<&str>>();
+        let candidates = (0..self.segments.len())
+            .filter(|&i| !merging.contains(self.manifest.segments[i].name.as_str()))
+            .collect::<Vec<usize>>();
+        let sizes = candidates
+            .iter()
+            .map(|&i| SegmentSize {
+                doc_count: self.segments[i].index.doc_count(),
+                max_doc_id: self.segments[i].index.max_doc_id(),
+            })
+            .collect::<Vec<SegmentSize>>();
+        for merge in self.merge_policy.find_merges(&sizes) {
+            let sources = merge
+                .iter()
+                .map(|&i| self.manifest.segments[candidates[i]].clone())
+                .collect::<Vec<SegmentInfo>>();
+            let target = SegmentInfo::new(self.manifest.next_segment);
+            self.manifest.next_segment += 1;
+            let directory_path = self.directory_path.to_path_buf();
+            let (merge_sources, merge_target) = (sources.clone(), target.clone());
+            let handle = thread::spawn(move || {
+                merge_segments(&directory_path, &merge_sources, &merge_target)
+            });
+            self.merges.push(PendingMerge {
+                sources,
+                target,
+                handle,
+            });
+        }
+    }
+
+    /// Replaces the segments of the finished merges, or of all the merges if `wait` is
+    /// set, by the merged segments.
+    fn finish_merges(&mut self, wait: bool) -> io::Result<()> {
+        let mut result = Ok(());
+        let mut i = 0;
+        while i < self.merges.len() {
+            if !wait && !self.merges[i].handle.is_finished() {
+                i += 1;
+                continue;
+            }
+            let merge = self.merges.remove(i);
+            let output = merge
+                .handle
+                .join()
+                .unwrap_or_else(|_| Err(io::Error::other("merge thread panicked")));
+            match output {
+                Ok((index, doc_maps)) => {
+                    self.install_merge(&merge.sources, merge.target, index, doc_maps)
+                }
+                Err(err) => result = Err(err),
+            }
+        }
+        result
+    }
+
+    /// Replaces the segments of a merge by the merged segment. The documents deleted from
+    /// them since the merge started are deleted from the merged segment.
+    fn install_merge(
+        &mut self,
+        sources: &[SegmentInfo],
+        target: SegmentInfo,
+        mut index: Index,
+        doc_maps: Vec<Vec<Option<usize>>>,
+    ) {
+        let mut deleted = false;
+        let mut position = None;
+        for (source, doc_map) in sources.iter().zip(&doc_maps) {
+            // Segments whose documents were all deleted are no longer in the manifest.
+            let current = self
+                .manifest
+                .segments
+                .iter()
+                .position(|info| info.name == source.name);
+            for (i, new_doc_id) in doc_map.iter().enumerate() {
+                if let Some(new_doc_id) = *new_doc_id {
+                    if !current.is_some_and(|j| self.segments[j].index.is_live(i + 1)) {
+                        deleted |= index.delete_document(new_doc_id);
+                    }
+                }
+            }
+            if let Some(j) = current {
+                self.manifest.segments.remove(j);
+                self.segments.remove(j);
+                position = Some(position.map_or(j, |position: usize| position.min(j)));
+            }
+        }
+        let position = position.unwrap_or(self.segments.len());
+        self.manifest.segments.insert(position, target);
+        self.segments
+            .insert(position, WriterSegment { index, deleted });
+    }
+
+    /// Removes the segment files that the last commit does not use, and the temporary
+    /// files left behind by a writer that crashed. Readers opening a previous commit
+    /// meanwhile read the new manifest again.
+    fn remove_unused_files(&self) -> io::Result<()> {
+        let mut used = self
+            .manifest
+            .segments
+            .iter()
+            .chain(
+                self.merges
+                    .iter()
+                    .flat_map(|merge| merge.sources.iter().chain([&merge.target])),
+            )
+            .flat_map(SegmentInfo::files)
+            .collect::<HashSet<String>>();
+        // The running merges write their segment files to temporary files first.
+        used.extend(
+            self.merges
+                .iter()
+                .map(|merge| format!("{}.{}", merge.target.name, TEMP_EXTENSION)),
+        );
+        for entry in fs::read_dir(self.directory_path)? {
+            let path = entry?.path();
+            let name = match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) => name,
+                None => continue,
+            };
+            let is_unused = match path.extension().and_then(|extension| extension.to_str()) {
+                Some(TEMP_EXTENSION) => !used.contains(name),
+                Some(extension) => {
+                    [
+                        SEGMENT_EXTENSION,
+                        POSTINGS_EXTENSION,
+                        STORED_FIELDS_EXTENSION,
+                        LIVE_DOCS_EXTENSION,
+                    ]
+                    .contains(&extension)
+                        && name.starts_with('_')
+                        && !used.contains(name)
+                }
+                None => false,
+            };
+            if is_unused {
+                fs::remove_file(&path)?;
+            }
+        }
         Ok(())
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl Drop for IndexWriter<'_> {
+    /// Waits for the running merges, so that they do not write to the directory once the
+    /// writer is gone. Their results are discarded.
+    fn drop(&mut self) {
+        for merge in self.merges.drain(..) {
+            let _ = merge.handle.join();
+        }
+    }
+}
+
+/// Reads the segments of a merge from `directory_path`, merges them and writes the merged
+/// segment as `target`.
+fn merge_segments(
+    directory_path: &Path,
+    sources: &[SegmentInfo],
+    target: &SegmentInfo,
+) -> io::Result<MergeOutput> {
+    let segments = sources
+        .iter()
+        .map(|info| {
+            let segment = Index::read_segment(directory_path, info)?;
+            // A corrupted segment must not be copied into a new one with a valid checksum.
+            segment.check_integrity()?;
+            Ok(segment)
+        })
+        .collect::<Result<Vec<Index>, IndexError>>()?;
+    let (merged, doc_maps) = Index::merge(&segments)?;
+    merged.write_segment(directory_path, target)?;
+    Ok((merged, doc_maps))
+}
+
+/// Writes the bincode encoding of a value to a file atomically. See `write_bytes`.
+pub(crate) fn write_file<T: Serialize>(path: &Path, kind: FileKind, value: &T) -> io::Result<()> {
+    write_bytes(path, kind, &bincode::serialize(value).unwrap())
+}
+
+/// Writes a file atomically, by writing a temporary file and renaming it. The bytes are
+/// framed by a header and a checksum, see `format::FORMAT_VERSION`. The rename is only
+/// durable once the directory is synced, see `sync_directory`.
+pub(crate) fn write_bytes(path: &Path, kind: FileKind, bytes: &[u8]) -> io::Result<()> {
+    let header = format::header(kind);
+    let mut checksum = crc32fast::Hasher::new();
+    checksum.update(&header);
+    checksum.update(bytes);
+    let temp_path = path.with_extension(TEMP_EXTENSION);
+    let mut file = File::create(&temp_path)?;
+    file.write_all(&header)?;
+    file.write_all(bytes)?;
+    file.write_all(&checksum.finalize().to_le_bytes())?;
+    file.sync_all()?;
+    fs::rename(&temp_path, path)
+}
+
+/// Flushes the entries of a directory to disk, so that the files created or renamed in it
+/// survive a crash. Does nothing on the platforms where directories cannot be opened as
+/// files.
+fn sync_directory(path: &Path) -> io::Result<()> {
+    if cfg!(unix) {
+        File::open(path)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// The error reported when the postings list of a term cannot be decoded.
+fn corrupted_postings(term: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("corrupted postings list of {:?}", term),
+    )
+}
+
+/// Reads a file written by `write_file`, after checking its header and its checksum.
+pub(crate) fn read_file<T: DeserializeOwned>(path: &Path, kind: FileKind) -> Result<T, IndexError> {
+    let buffer = fs::read(path).map_err(|source| IndexError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    bincode::deserialize(format::check_file(path, kind, &buffer)?)
+        .map_err(|err| IndexError::corrupted(path.to_path_buf(), err.to_string()))
+}
+
+/// Number of postings per block of a postings list.
+pub const BLOCK_SIZE: usize = 128;
+
+/// Postings of a term, compressed in blocks of `BLOCK_SIZE` postings. The doc_id gaps and
+/// the freqs of the postings are interleaved in one variable-byte encoded stream, and the
+/// gaps between the positions of each posting in another one, so that the positions are
+/// only decoded when they are needed.
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PostingsList {
-    docs: Vec<usize>,
-    freqs: Vec<u32>,
+    len: usize,
+    last_doc: usize,
+    doc_data: Bytes,      // doc_id gap and freq of each posting
+    position_data: Bytes, // position gaps of each posting
+    collection_freq: u64, // sum of freqs
+    impacts: Impacts,
+    blocks: Vec<Block>, // skip data of each BLOCK_SIZE postings
 }
 
 impl PostingsList {
     pub fn new() -> Self {
         PostingsList {
-            docs: vec![],
-            freqs: vec![],
+            len: 0,
+            last_doc: 0,
+            doc_data: Bytes::new(),
+            position_data: Bytes::new(),
+            collection_freq: 0,
+            impacts: Impacts::new(),
+            blocks: vec![],
         }
     }
 
     pub fn len(&self) -> usize {
-        self.docs.len()
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Appends a posting. Postings must be added in increasing doc_id order, with sorted
+    /// positions.
+    pub fn add(&mut self, doc_id: usize, positions: Vec<u32>, doc_length: u32) {
+        assert!(
+            self.len == 0 || doc_id > self.last_doc,
+            "doc_id {} added after {}",
+            doc_id,
+            self.last_doc
+        );
+        let freq = positions.len() as u32;
+        if self.len.is_multiple_of(BLOCK_SIZE) {
+            // The first gap of a block is relative to the last doc_id of the previous
+            // block, so that blocks can be decoded independently.
+            self.blocks.push(Block {
+                last_doc: doc_id,
+                doc_offset: self.doc_data.len(),
+                position_offset: self.position_data.len(),
+                impacts: Impacts::new(),
+            });
+        }
+        let doc_data = self.doc_data.to_mut();
+        encode_vbyte((doc_id - self.last_doc) as u64, doc_data);
+        encode_vbyte(freq as u64, doc_data);
+        let position_data = self.position_data.to_mut();
+        let mut last_position = 0;
+        for position in positions {
+            encode_vbyte((position - last_position) as u64, position_data);
+            last_position = position;
+        }
+
+        self.len += 1;
+        self.last_doc = doc_id;
+        self.collection_freq += freq as u64;
+        self.impacts.add(freq, doc_length);
+        if let Some(block) = self.blocks.last_mut() {
+            block.last_doc = doc_id;
+            block.impacts.add(freq, doc_length);
+        }
+    }
+
+    /// Appends the postings list to `out`: a header with the skip data, followed by the
+    /// doc_id and position streams, which `decode` does not copy.
+    pub(crate) fn encode(&self, out: &mut Vec<u8>) {
+        let header = (
+            self.len,
+            self.last_doc,
+            self.collection_freq,
+            &self.impacts,
+            &self.blocks,
+            self.doc_data.len(),
+            self.position_data.len(),
+        );
+        bincode::serialize_into(&mut *out, &header).unwrap();
+        out.extend_from_slice(&self.doc_data);
+        out.extend_from_slice(&self.position_data);
+    }
+
+    /// Decodes a postings list encoded by `encode`. Only the header is decoded: the
+    /// streams are slices of `bytes`. Returns `None` if the header is inconsistent, so
+    /// that the postings are decoded within the bounds of the streams and of the blocks.
+    pub(crate) fn decode(bytes: &Bytes) -> Option<Self> {
+        let mut reader = &bytes[..];
+        let (len, last_doc, collection_freq, impacts, blocks, doc_len, position_len): (
+            usize,
+            usize,
+            u64,
+            Impacts,
+            Vec<Block>,
+            usize,
+            usize,
+        ) = bincode::deserialize_from(&mut reader).ok()?;
+        let doc_start = bytes.len() - reader.len();
+        let position_start = doc_start.checked_add(doc_len)?;
+        if blocks.len() != len.div_ceil(BLOCK_SIZE)
+            || blocks.last().map_or(0, |block| block.last_doc) != last_doc
+            || blocks.first().is_some_and(|block| {
+                block.last_doc == 0 || block.doc_offset != 0 || block.position_offset != 0
+            })
+            || blocks.windows(2).any(|pair| {
+                pair[0].last_doc >= pair[1].last_doc
+                    || pair[0].doc_offset >= pair[1].doc_offset
+                    || pair[0].position_offset > pair[1].position_offset
+            })
+            || blocks
+                .iter()
+                .any(|block| block.doc_offset >= doc_len || block.position_offset > position_len)
+        {
+            return None;
+        }
+        Some(PostingsList {
+            len,
+            last_doc,
+            doc_data: bytes.slice(doc_start..position_start)?,
+            position_data: bytes
+                .slice(position_start..position_start.checked_add(position_len)?)?,
+            collection_freq,
+            impacts,
+            blocks,
+        })
+    }
+
+    /// Upper bound of the score of the term in any document of this postings list.
+    pub fn max_score(&self, similarity: &dyn Similarity, stats: &TermStats) -> f64 {
+        self.impacts.max_score(similarity, stats)
+    }
+
+    pub fn collection_freq(&self) -> u64 {
+        self.collection_freq
     }
 
-    pub fn add(&mut self, doc_id: usize, freq: u32) {
-        self.docs.push(doc_id);
-        self.freqs.push(freq);
+    /// Returns the index of the first block, starting from the block of the posting at
+    /// `from`, whose last doc_id is `>= target`. Returns the number of blocks if there is
+    /// no such block.
+    pub fn find_block(&self, from: usize, target: usize) -> usize {
+        let from_block = (from / BLOCK_SIZE).min(self.blocks.len());
+        from_block + self.blocks[from_block..].partition_point(|block| block.last_doc < target)
     }
 
-    pub fn get_doc_id(&self, index: usize) -> Option<usize> {
-        self.docs.get(index).map(|doc_id| *doc_id)
+    pub fn get_block(&self, block: usize) -> Option<&Block> {
+        self.blocks.get(block)
+    }
+
+    /// The doc_ids of all postings, or `None` if they cannot be decoded.
+    pub fn doc_ids(&self) -> Option<Vec<usize>> {
+        let (mut doc_ids, mut docs, mut freqs) = (Vec::with_capacity(self.len), vec![], vec![]);
+        for block in 0..self.blocks.len() {
+            self.decode_block(block, &mut docs, &mut freqs)?;
+            doc_ids.extend_from_slice(&docs);
+        }
+        Some(doc_ids)
+    }
+
+    /// Decodes the doc_ids and the freqs of the postings of a block into `docs` and
+    /// `freqs`, replacing their contents. Returns `None` if there is no such block, or if
+    /// its postings cannot be decoded or do not end at the last doc_id of the block.
+    pub fn decode_block(
+        &self,
+        block: usize,
+        docs: &mut Vec<usize>,
+        freqs: &mut Vec<u32>,
+    ) -> Option<()> {
+        docs.clear();
+        freqs.clear();
+        let last_doc = self.blocks.get(block)?.last_doc;
+        let mut offset = self.blocks[block].doc_offset;
+        let mut doc_id = if block == 0 {
+            0
+        } else {
+            self.blocks[block - 1].last_doc
+        };
+        let len = BLOCK_SIZE.min(self.len.checked_sub(block * BLOCK_SIZE)?);
+        for _ in 0..len {
+            let gap = usize::try_from(decode_vbyte(&self.doc_data, &mut offset)?).ok()?;
+            doc_id = doc_id
+                .checked_add(gap)
+                .filter(|&doc_id| gap > 0 && doc_id <= last_doc)?;
+            docs.push(doc_id);
+            freqs.push(u32::try_from(decode_vbyte(&self.doc_data, &mut offset)?).ok()?);
+        }
+        (doc_id == last_doc).then_some(())
+    }
+
+    /// Decodes the positions of the `index`-th posting of a block, given the decoded
+    /// freqs of the block. Returns `None` if they cannot be decoded.
+    pub fn decode_positions(&self, block: usize, freqs: &[u32], index: usize) -> Option<Vec<u32>> {
+        let mut offset = self.blocks.get(block)?.position_offset;
+        let skipped = freqs[..index]
+            .iter()
+            .map(|&freq| freq as usize)
+            .sum::<usize>();
+        for _ in 0..skipped {
+            decode_vbyte(&self.position_data, &mut offset)?;
+        }
+        let mut position = 0;
+        (0..freqs[index])
+            .map(|_| {
+                position = decode_position(&self.position_data, &mut offset, position)?;
+                Some(position)
+            })
+            .collect()
+    }
+
+    /// Decodes the positions of all the postings of a block, given the decoded freqs of
+    /// the block. Returns `None` if they cannot be decoded.
+    pub fn decode_block_positions(&self, block: usize, freqs: &[u32]) -> Option<Vec<Vec<u32>>> {
+        let mut offset = self.blocks.get(block)?.position_offset;
+        freqs
+            .iter()
+            .map(|&freq| {
+                let mut position = 0;
+                (0..freq)
+                    .map(|_| {
+                        position = decode_position(&self.position_data, &mut offset, position)?;
+                        Some(position)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Decodes the gap from the previous position of a posting, and returns the position.
+fn decode_position(data: &[u8], offset: &mut usize, previous: u32) -> Option<u32> {
+    previous.checked_add(u32::try_from(decode_vbyte(data, offset)?).ok()?)
+}
+
+impl Default for PostingsList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Skip data of a block of postings.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Block {
+    pub last_doc: usize,        // largest doc_id in the block
+    pub doc_offset: usize,      // start of the block in the doc_id and freq stream
+    pub position_offset: usize, // start of the block in the position stream
+    pub impacts: Impacts,
+}
+
+/// Pareto frontier of the `(freq, doc_length)` pairs of a set of postings: no pair has
+/// both a lower-or-equal freq and a longer-or-equal document than another one. Since
+/// scores never decrease with freq nor increase with doc_length, the best scoring posting
+/// of the set has one of these pairs, whatever the similarity.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct Impacts {
+    pairs: Vec<(u32, u32)>,
+}
+
+impl Impacts {
+    pub fn new() -> Self {
+        Impacts { pairs: vec![] }
+    }
+
+    pub fn add(&mut self, freq: u32, doc_length: u32) {
+        if self
+            .pairs
+            .iter()
+            .any(|&(f, l)| f >= freq && l <= doc_length)
+        {
+            return;
+        }
+        self.pairs.retain(|&(f, l)| !(freq >= f && doc_length <= l));
+        self.pairs.push((freq, doc_length));
+    }
+
+    pub fn max_score(&self, similarity: &dyn Similarity, stats: &TermStats) -> f64 {
+        self.pairs
+            .iter()
+            .map(|&(freq, doc_length)| similarity.score(stats, freq, doc_length))
+            .fold(0.0, f64::max)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Index;
+    use std::ops::Bound;
+
+    use super::{Impacts, Index, IndexError, IndexWriter, PostingsList, Terms, BLOCK_SIZE};
+    use crate::analyzer::synonym::SynonymMap;
+    use crate::analyzer::{AnalyzerConfig, TokenFilterConfig, TokenizerConfig};
+    use crate::bytes::Bytes;
+    use crate::commit::{Commit, SegmentInfo};
+    use crate::merge::{NoMergePolicy, TieredMergePolicy};
+    use crate::query::{phrase::PhraseQuery, term::TermQuery, Query};
+    use crate::schema::{Document, FieldEntry, Schema, DEFAULT_FIELD};
+    use crate::searcher::Searcher;
+    use crate::similarity::{Bm25, Similarity, TermStats};
+    use crate::terms::automaton::{Automaton, Str};
+    use crate::testing::TempDir;
+
+    fn collect(terms: impl Iterator<Item = Result<String, IndexError>>) -> Vec<String> {
+        terms.collect::<Result<_, _>>().unwrap()
+    }
+
+    /// Writes `index` as a segment and reads it back.
+    fn reopen(index: &Index, name: &str) -> Index {
+        let directory = TempDir::new(name);
+        let info = SegmentInfo::new(0);
+        index.write_segment(directory.path(), &info).unwrap();
+        Index::read_segment(directory.path(), &info).unwrap()
+    }
+
+    fn document(id: &str, title: &str) -> Document {
+        Document::new()
+            .with_value("id", id)
+            .with_value("title", title)
+    }
 
     #[test]
     fn test_index() {
         let index = {
             let mut index = Index::new();
             assert_eq!(index.max_doc_id, 0);
-            index.add(&String::from("two one two"));
+            index.add(&String::from("two one two")).unwrap();
             assert_eq!(index.max_doc_id, 1);
-            index.add(&String::from("one two three two three three"));
+            index
+                .add(&String::from("one two three two three three"))
+                .unwrap();
             assert_eq!(index.max_doc_id, 2);
             index
         };
 
-        let posting_list_one = index.inverted_index.get(&"one".to_string()).unwrap();
+        assert_eq!(index.doc_count(), 2);
+        let field = index.field(DEFAULT_FIELD).unwrap();
+        assert_eq!(field.doc_count(), 2);
+        assert_eq!(field.doc_length(1), 3);
+        assert_eq!(field.doc_length(2), 6);
+        assert_eq!(field.avg_doc_length(), 4.5);
+        assert_eq!(field.total_length(), 9);
+        assert_eq!(field.doc_freq("two"), 2);
+        assert_eq!(field.collection_freq("two"), 4);
+        assert_eq!(field.doc_freq("four"), 0);
+        assert_eq!(field.collection_freq("four"), 0);
+
+        let posting_list_one = field.get_postings_list("one").unwrap();
         assert_eq!(posting_list_one.len(), 2);
 
-        let posting_list_of_two = index.inverted_index.get(&"two".to_string()).unwrap();
+        let posting_list_of_two = field.get_postings_list("two").unwrap();
         assert_eq!(posting_list_of_two.len(), 2);
 
-        let posting_list_of_three = index.inverted_index.get(&"three".to_string()).unwrap();
+        let posting_list_of_three = field.get_postings_list("three").unwrap();
         assert_eq!(posting_list_of_three.len(), 1);
+        let (mut docs, mut freqs) = (vec![], vec![]);
+        posting_list_of_three
+            .decode_block(0, &mut docs, &mut freqs)
+            .unwrap();
+        assert_eq!(docs, vec![2]);
+        assert_eq!(freqs, vec![3]);
+        assert_eq!(
+            posting_list_of_three
+                .decode_positions(0, &freqs, 0)
+                .unwrap(),
+            vec![2, 4, 5]
+        );
+    }
+
+    #[test]
+    fn test_index_analyzer() {
+        let config = AnalyzerConfig::new(
+            TokenizerConfig::Whitespace,
+            vec![
+                TokenFilterConfig::StripPunctuation,
+                TokenFilterConfig::Lowercase,
+            ],
+        );
+        let mut index = Index::with_analyzer(config.clone());
+        index.add("Hello, hello world!").unwrap();
+        let field = index.field(DEFAULT_FIELD).unwrap();
+        assert_eq!(field.doc_freq("hello"), 1);
+        assert_eq!(field.collection_freq("hello"), 2);
+        assert_eq!(field.doc_freq("world"), 1);
+        assert_eq!(field.doc_freq("Hello,"), 0);
+
+        let decoded = reopen(&index, "index-analyzer");
+        let (entry, field) = decoded.resolve_field(None).unwrap();
+        assert_eq!(entry.analyzer().config(), &config);
+        assert_eq!(field.doc_freq("hello"), 1);
+    }
+
+    #[test]
+    fn test_index_schema() {
+        let lowercase = AnalyzerConfig::new(
+            TokenizerConfig::UnicodeWord,
+            vec![TokenFilterConfig::Lowercase],
+        );
+        let schema = Schema::new()
+            .with_field(FieldEntry::new("id").with_indexed(false).with_stored(true))
+            .with_field(FieldEntry::new("title").with_analyzer(lowercase.clone()))
+            .with_field(FieldEntry::new("body").with_analyzer(lowercase))
+            .with_field(FieldEntry::new("tags"));
+        let mut index = Index::with_schema(schema);
+        index
+            .add_document(
+                &Document::new()
+                    .with_value("id", "a")
+                    .with_value("title", "Shetland Sheepdog")
+                    .with_value("body", "A small herding dog.")
+                    .with_value("tags", "dog")
+                    .with_value("tags", "herding"),
+            )
+            .unwrap();
+        index
+            .add_document(
+                &Document::new()
+                    .with_value("id", "b")
+                    .with_value("title", "Herding")
+                    .with_value("unknown", "dog"),
+            )
+            .unwrap();
+        index.add("sheepdog").unwrap();
+
+        assert_eq!(index.doc_count(), 3);
+        assert!(index.field("id").is_none());
+        assert!(index.field("unknown").is_none());
+
+        // Each field has its own postings and lengths.
+        let title = index.field("title").unwrap();
+        assert_eq!(title.doc_freq("herding"), 1);
+        assert_eq!(title.doc_freq("sheepdog"), 2);
+        assert_eq!(title.doc_length(1), 2);
+        let body = index.field("body").unwrap();
+        assert_eq!(body.doc_freq("herding"), 1);
+        assert_eq!(body.doc_length(2), 0);
+        assert_eq!(body.doc_length(3), 0);
+        let tags = index.field("tags").unwrap();
+        assert_eq!(tags.doc_freq("herding"), 1);
+        assert_eq!(tags.doc_length(1), 2);
+
+        let doc_ids = |query: &dyn Query| {
+            let mut doc_ids = query
+                .execute(&index)
+                .iter()
+                .map(|r| r.doc_id)
+                .collect::<Vec<usize>>();
+            doc_ids.sort_unstable();
+            doc_ids
+        };
+        // Queries search the first indexed field unless told otherwise.
+        assert_eq!(doc_ids(&TermQuery::new("herding".to_string())), vec![2]);
+        assert_eq!(
+            doc_ids(&TermQuery::new("herding".to_string()).with_field("body")),
+            vec![1]
+        );
+        assert!(doc_ids(&TermQuery::new("a".to_string()).with_field("id")).is_empty());
+        // Phrases do not match across the values of a field.
+        let phrase = |terms: &[&str]| {
+            PhraseQuery::new(terms.iter().map(|t| t.to_string()).collect()).with_field("tags")
+        };
+        assert_eq!(doc_ids(&phrase(&["dog"])), vec![1]);
+        assert!(doc_ids(&phrase(&["dog", "herding"])).is_empty());
+
+        let searcher = Searcher::new(&index);
+        assert_eq!(searcher.search("Sheepdog", 10).len(), 2);
+        assert_eq!(searcher.search_field("body", "dog", 10).len(), 1);
+        assert!(searcher.search_field("unknown", "dog", 10).is_empty());
+
+        let decoded = reopen(&index, "index-schema");
+        assert_eq!(decoded.schema().fields().len(), 4);
+        assert_eq!(decoded.field("tags").unwrap().doc_freq("dog"), 1);
+    }
+
+    #[test]
+    fn test_index_synonyms() {
+        // "a b" is rewritten to "b" at position 0, after "b" was kept at position 1.
+        let config = AnalyzerConfig::new(
+            TokenizerConfig::Whitespace,
+            vec![TokenFilterConfig::Synonyms(
+                SynonymMap::parse("a b, b").unwrap(),
+            )],
+        );
+        let mut index = Index::with_analyzer(config);
+        index.add("a b").unwrap();
+        let field = index.field(DEFAULT_FIELD).unwrap();
+        assert_eq!(field.doc_freq("b"), 1);
+        assert_eq!(field.collection_freq("b"), 2);
+
+        let postings_list = field.get_postings_list("b").unwrap();
+        let (mut docs, mut freqs) = (vec![], vec![]);
+        postings_list
+            .decode_block(0, &mut docs, &mut freqs)
+            .unwrap();
+        assert_eq!(
+            postings_list.decode_positions(0, &freqs, 0).unwrap(),
+            vec![0, 1]
+        );
+    }
+
+    #[test]
+    fn test_mapped_segment() {
+        let mut index = Index::new();
+        for i in 0..300 {
+            index
+                .add(if i % 3 == 0 {
+                    "rough collie"
+                } else {
+                    "smooth collie"
+                })
+                .unwrap();
+        }
+        let mut segment = reopen(&index, "mapped-segment");
+        let field = segment.field(DEFAULT_FIELD).unwrap();
+        assert!(matches!(field.terms, Terms::Mapped(_)));
+        assert_eq!(collect(field.terms()), vec!["collie", "rough", "smooth"]);
+        // Term searches give the same results in memory and once mapped.
+        let memory = index.field(DEFAULT_FIELD).unwrap();
+        for field in [memory, field] {
+            assert_eq!(
+                collect(field.search_terms(Str::new("s").starts_with())),
+                vec!["smooth"]
+            );
+            assert_eq!(
+                collect(field.search_terms(Str::new("rough").complement())),
+                vec!["collie", "smooth"]
+            );
+            let range = |start, end| collect(field.terms_in_range(start, end));
+            assert_eq!(
+                range(Bound::Excluded("collie"), Bound::Included("smooth")),
+                vec!["rough", "smooth"]
+            );
+            assert_eq!(
+                range(Bound::Unbounded, Bound::Excluded("rough")),
+                vec!["collie"]
+            );
+            assert!(range(Bound::Included("s"), Bound::Excluded("s")).is_empty());
+            assert!(range(Bound::Included("t"), Bound::Included("s")).is_empty());
+            assert_eq!(
+                range(Bound::Included("rough"), Bound::Included("rough")).len(),
+                1
+            );
+        }
+        assert_eq!(field.doc_freq("rough"), 100);
+        assert_eq!(field.collection_freq("collie"), 300);
+        assert_eq!(field.doc_freq("corgi"), 0);
+        let rough = field.get_postings_list("rough").unwrap();
+        assert_eq!(
+            rough.doc_ids().unwrap(),
+            (1..=300).step_by(3).collect::<Vec<usize>>()
+        );
+        assert_eq!(
+            TermQuery::new("smooth".to_string()).execute(&segment).len(),
+            200
+        );
+        assert_eq!(Searcher::new(&segment).search("rough collie", 10).len(), 10);
+
+        // Adding documents to a segment that was read copies its postings into memory.
+        segment.add("rough collie").unwrap();
+        let field = segment.field(DEFAULT_FIELD).unwrap();
+        assert!(matches!(field.terms, Terms::Memory(_)));
+        assert_eq!(field.doc_freq("rough"), 101);
+        assert_eq!(
+            field.get_postings_list("rough").unwrap().doc_ids().unwrap()[100],
+            301
+        );
+    }
+
+    #[test]
+    fn test_unique_key() {
+        let schema = Schema::new()
+            .with_field(FieldEntry::new("title"))
+            .with_unique_key("id");
+        let mut index = Index::with_schema(schema);
+        assert_eq!(index.add_document(&document("a", "sheltie")).unwrap(), 1);
+        assert_eq!(index.add_document(&document("b", "sheltie")).unwrap(), 2);
+
+        // Documents without a key, with several keys or with a taken key are rejected.
+        assert!(index
+            .add_document(&Document::new().with_value("title", "x"))
+            .is_err());
+        assert!(index
+            .add_document(&document("c", "sheltie").with_value("id", "d"))
+            .is_err());
+        assert!(index.add_document(&document("a", "sheltie")).is_err());
+        let err = index.add("sheltie").unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert_eq!(index.doc_count(), 2);
+        assert_eq!(index.add_document(&document("c", "sheltie")).unwrap(), 3);
+
+        assert_eq!(index.doc_id("b"), Some(2));
+        assert_eq!(index.doc_id("x"), None);
+        assert_eq!(index.external_id(3), Some("c"));
+        assert_eq!(index.external_id(0), None);
+        assert_eq!(index.external_id(4), None);
+
+        // The key is not stored unless the schema says so.
+        assert_eq!(index.doc(1), Some(Document::new()));
+
+        let encoded = bincode::serialize(&index).unwrap();
+        let decoded: Index = bincode::deserialize(&encoded).unwrap();
+        assert_eq!(decoded.doc_id("c"), Some(3));
+        assert_eq!(decoded.schema().unique_key(), Some("id"));
+    }
+
+    #[test]
+    fn test_commit() {
+        let schema = Schema::new()
+            .with_field(FieldEntry::new("id").with_indexed(false).with_stored(true))
+            .with_field(FieldEntry::new("title").with_stored(true))
+            .with_field(FieldEntry::new("body"));
+        let directory = TempDir::new("commit");
+        let directory_path = directory.path().to_path_buf();
+
+        let mut writer = IndexWriter::with_schema(&directory_path, schema).unwrap();
+        for i in 1..=100 {
+            let doc_id = writer
+                .add(
+                    &document(&format!("doc-{}", i), &format!("title {}", i))
+                        .with_value("body", "body"),
+                )
+                .unwrap();
+            assert_eq!(doc_id, i);
+        }
+        writer.commit().unwrap();
+        assert!(directory_path.join("_0.store").exists());
+
+        // The files of the commit are mapped, and stay readable once removed.
+        let commit = Commit::open(&directory_path).unwrap();
+        drop(directory);
+        let index = &commit.segments()[0];
+        assert_eq!(index.doc_count(), 100);
+        // Only the stored fields are returned.
+        assert_eq!(index.doc(42).unwrap(), document("doc-42", "title 42"));
+        let searcher = commit.searcher();
+        let results = searcher.search("7", 10);
+        assert_eq!(results.len(), 1);
+        assert_eq!(
+            searcher.doc(results[0].doc_id).unwrap().get_first("id"),
+            Some("doc-7")
+        );
+        assert_eq!(searcher.doc(0), None);
+        assert_eq!(searcher.doc(101), None);
+
+        assert!(Commit::open(&directory_path).is_err());
+    }
+
+    #[test]
+    fn test_delete() {
+        let schema = Schema::new()
+            .with_field(FieldEntry::new("id").with_indexed(false).with_stored(true))
+            .with_field(FieldEntry::new("title"))
+            .with_unique_key("id");
+        let directory = TempDir::new("delete");
+        let directory_path = directory.path();
+
+        let mut writer = IndexWriter::with_schema(directory_path, schema)
+            .unwrap()
+            .with_merge_policy(NoMergePolicy);
+        for (id, title) in [
+            ("a", "sheltie dog"),
+            ("b", "collie dog"),
+            ("c", "sheltie collie"),
+            ("d", "corgi dog"),
+        ] {
+            writer.add(&document(id, title)).unwrap();
+        }
+        assert!(writer.delete_by_id("a"));
+        assert!(!writer.delete_by_id("a"));
+        assert!(!writer.delete_by_id("x"));
+        assert_eq!(writer.delete_by_term("title", "corgi").unwrap(), 1);
+        assert_eq!(writer.delete_by_term("title", "dog").unwrap(), 1);
+        assert_eq!(writer.delete_by_term("title", "poodle").unwrap(), 0);
+        assert_eq!(writer.delete_by_term("body", "dog").unwrap(), 0);
+        writer.commit().unwrap();
+        assert!(directory_path.join("_0_1.liv").exists());
+
+        let (_, mut segments) = Commit::open(directory_path).unwrap().into_parts();
+        let mut index = segments.remove(0);
+        assert_eq!(index.doc_count(), 1);
+        assert_eq!(index.max_doc_id(), 4);
+        assert!(!index.is_live(1) && index.is_live(3));
+        assert_eq!(index.doc(1), None);
+        assert_eq!(index.doc(3).unwrap().get_first("id"), Some("c"));
+        assert_eq!(index.doc_id("a"), None);
+        assert_eq!(index.external_id(2), None);
+
+        // Deleted documents match neither queries nor searches.
+        let query = TermQuery::new("collie".to_string());
+        assert_eq!(
+            query
+                .execute(&index)
+                .iter()
+                .map(|result| result.doc_id)
+                .collect::<Vec<usize>>(),
+            vec![3]
+        );
+        assert!(
+            PhraseQuery::new(vec!["sheltie".to_string(), "dog".to_string()])
+                .execute(&index)
+                .is_empty()
+        );
+        let searcher = Searcher::new(&index);
+        assert_eq!(
+            searcher
+                .search("sheltie collie dog", 10)
+                .iter()
+                .map(|result| result.doc_id)
+                .collect::<Vec<usize>>(),
+            vec![3]
+        );
+        assert_eq!(searcher.search_query(&query, 10).len(), 1);
+
+        // The unique key of a deleted document can be reused.
+        let doc_id = index.add_document(&document("a", "dog")).unwrap();
+        assert_eq!(doc_id, 5);
+        assert_eq!(index.doc_id("a"), Some(5));
+        assert_eq!(index.doc_count(), 2);
+    }
+
+    #[test]
+    fn test_update_document() {
+        let schema = Schema::new()
+            .with_field(FieldEntry::new("id").with_indexed(false).with_stored(true))
+            .with_field(FieldEntry::new("title").with_stored(true))
+            .with_unique_key("id");
+        let directory = TempDir::new("update");
+        let directory_path = directory.path();
+
+        let mut writer = IndexWriter::with_schema(directory_path, schema).unwrap();
+        writer.add(&document("a", "rough collie")).unwrap();
+        writer.add(&document("b", "sheltie")).unwrap();
+        writer.commit().unwrap();
+        let before = Commit::open(directory_path).unwrap();
+
+        let mut writer = IndexWriter::open(directory_path)
+            .unwrap()
+            .with_merge_policy(NoMergePolicy);
+        assert_eq!(
+            writer
+                .update_document("a", &document("a", "smooth collie"))
+                .unwrap(),
+            1
+        );
+        // Documents that do not have the key are rejected.
+        assert!(writer
+            .update_document("b", &document("c", "corgi"))
+            .is_err());
+        assert!(writer
+            .update_document("b", &document("b", "corgi").with_value("id", "c"))
+            .is_err());
+        // Documents with a new key are added.
+        assert_eq!(
+            writer
+                .update_document("d", &document("d", "corgi"))
+                .unwrap(),
+            2
+        );
+        // Readers do not see the changes until they are committed.
+        assert_eq!(Commit::open(directory_path).unwrap().generation(), 1);
+        writer.commit().unwrap();
+        let after = Commit::open(directory_path).unwrap();
+
+        // Readers see either version, never both or none.
+        let titles = |commit: &Commit| {
+            let searcher = commit.searcher();
+            searcher
+                .search_query(&TermQuery::new("collie".to_string()), 10)
+                .iter()
+                .map(|result| {
+                    searcher
+                        .doc(result.doc_id)
+                        .unwrap()
+                        .get_first("title")
+                        .unwrap()
+                        .to_string()
+                })
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(titles(&before), vec!["rough collie"]);
+        assert_eq!(titles(&after), vec!["smooth collie"]);
+        assert_eq!(before.doc_count(), 2);
+        assert_eq!(after.doc_count(), 3);
+        assert_eq!(after.searcher().doc_id("a"), Some(3));
+
+        let mut index = Index::new();
+        assert!(index.update_document("a", &Document::new()).is_err());
+    }
+
+    #[test]
+    fn test_merge() {
+        let schema = Schema::new()
+            .with_field(FieldEntry::new("id").with_indexed(false).with_stored(true))
+            .with_field(FieldEntry::new("title"))
+            .with_unique_key("id");
+        let mut first = Index::with_schema(schema.clone());
+        first
+            .add_document(&document("a", "shetland sheepdog"))
+            .unwrap();
+        first.add_document(&document("b", "rough collie")).unwrap();
+        first.add_document(&document("c", "sheepdog")).unwrap();
+        first.delete_by_id("b");
+        let mut second = Index::with_schema(schema);
+        for i in 0..BLOCK_SIZE + 2 {
+            let title = if i % 2 == 0 {
+                "shetland sheepdog"
+            } else {
+                "corgi"
+            };
+            second
+                .add_document(&document(&format!("d{}", i), title))
+                .unwrap();
+        }
+        second.delete_by_id("d1");
+
+        let (merged, doc_maps) = Index::merge(&[first, second]).unwrap();
+        assert_eq!(doc_maps[0], vec![Some(1), None, Some(2)]);
+        assert_eq!(doc_maps[1][..3], [Some(3), None, Some(4)]);
+        assert_eq!(merged.doc_count(), BLOCK_SIZE + 3);
+        assert_eq!(merged.max_doc_id(), merged.doc_count());
+        assert_eq!(merged.doc_id("c"), Some(2));
+        assert_eq!(merged.doc_id("b"), None);
+        assert_eq!(merged.external_id(4), Some("d2"));
+        assert_eq!(merged.doc(4).unwrap().get_first("id"), Some("d2"));
+
+        let title = merged.field("title").unwrap();
+        assert_eq!(title.doc_freq("collie"), 0);
+        assert_eq!(title.doc_freq("sheepdog"), 2 + (BLOCK_SIZE + 2) / 2);
+        assert_eq!(title.doc_length(2), 1);
+        assert_eq!(
+            title.total_length(),
+            (3 + (BLOCK_SIZE + 2) / 2 * 3 - 1) as u64
+        );
+        let results =
+            PhraseQuery::new(vec!["shetland".to_string(), "sheepdog".to_string()]).execute(&merged);
+        assert_eq!(results.len(), 1 + (BLOCK_SIZE + 2) / 2);
+    }
+
+    #[test]
+    fn test_background_merge() {
+        let schema = Schema::new()
+            .with_field(FieldEntry::new("id").with_indexed(false).with_stored(true))
+            .with_field(FieldEntry::new("title"))
+            .with_unique_key("id");
+        let directory = TempDir::new("merge");
+        let directory_path = directory.path();
+
+        let mut writer = IndexWriter::with_schema(directory_path, schema)
+            .unwrap()
+            .with_merge_policy(TieredMergePolicy::new(3, 10, 40.0));
+        for ids in [["a", "b"], ["c", "d"], ["e", "f"]] {
+            for id in ids {
+                writer.add(&document(id, "sheltie")).unwrap();
+            }
+            writer.commit().unwrap();
+        }
+        // The third commit started a merge of the three segments. Documents deleted while
+        // it runs are deleted from the merged segment.
+        assert!(writer.delete_by_id("c"));
+        writer.wait_for_merges().unwrap();
+        assert_eq!(Commit::open(directory_path).unwrap().segments().len(), 3);
+        writer.commit().unwrap();
+
+        let commit = Commit::open(directory_path).unwrap();
+        assert_eq!(commit.segments().len(), 1);
+        assert_eq!(commit.manifest().segments()[0].name(), "_3");
+        assert_eq!(commit.doc_count(), 5);
+        let searcher = commit.searcher();
+        assert_eq!(searcher.search("sheltie", 10).len(), 5);
+        assert_eq!(searcher.doc_id("c"), None);
+        assert_eq!(searcher.doc_id("f"), Some(6));
+
+        // The files of the merged segments are removed.
+        let mut files = std::fs::read_dir(directory_path)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<String>>();
+        files.sort();
+        assert_eq!(
+            files,
+            vec!["_3.doc", "_3.post", "_3.store", "_3_4.liv", "manifest"]
+        );
+
+        // The merged segment is rewritten once too many of its documents are deleted.
+        for id in ["a", "b"] {
+            writer.delete_by_id(id);
+        }
+        writer.commit().unwrap();
+        writer.wait_for_merges().unwrap();
+        writer.commit().unwrap();
+        drop(writer);
+        let commit = Commit::open(directory_path).unwrap();
+        assert_eq!(commit.manifest().segments()[0].name(), "_4");
+        assert_eq!(commit.segments()[0].max_doc_id(), 3);
+        assert_eq!(commit.searcher().doc_id("f"), Some(3));
+    }
+
+    #[test]
+    fn test_impacts() {
+        let mut impacts = Impacts::new();
+        impacts.add(1, 10);
+        impacts.add(2, 10);
+        impacts.add(1, 20);
+        impacts.add(1, 5);
+        impacts.add(3, 30);
+        assert_eq!(impacts.pairs, vec![(2, 10), (1, 5), (3, 30)]);
+
+        let stats = TermStats {
+            doc_freq: 5,
+            collection_freq: 8,
+            doc_count: 10,
+            total_length: 100,
+            avg_doc_length: 10.0,
+        };
+        let bm25 = Bm25::default();
+        let max_score = [(1, 10), (2, 10), (1, 20), (1, 5), (3, 30)]
+            .iter()
+            .map(|&(freq, doc_length)| bm25.score(&stats, freq, doc_length))
+            .fold(0.0, f64::max);
+        assert_eq!(impacts.max_score(&bm25, &stats), max_score);
+    }
+
+    #[test]
+    fn test_postings_list_encoding() {
+        let mut postings_list = PostingsList::new();
+        let postings = (1..=1000)
+            .map(|i| {
+                (
+                    i * 7,
+                    (0..i as u32 % 4 + 1)
+                        .map(|p| p * 3 + i as u32 % 2)
+                        .collect(),
+                )
+            })
+            .collect::<Vec<(usize, Vec<u32>)>>();
+        for (doc_id, positions) in &postings {
+            postings_list.add(*doc_id, positions.clone(), 20);
+        }
+        assert_eq!(postings_list.len(), 1000);
+
+        let mut decoded = vec![];
+        let (mut docs, mut freqs) = (vec![], vec![]);
+        for block in 0..8 {
+            postings_list
+                .decode_block(block, &mut docs, &mut freqs)
+                .unwrap();
+            for (i, &doc_id) in docs.iter().enumerate() {
+                decoded.push((
+                    doc_id,
+                    postings_list.decode_positions(block, &freqs, i).unwrap(),
+                ));
+            }
+        }
+        assert_eq!(decoded, postings);
+
+        // Small gaps take a byte each, instead of 8 bytes per doc_id and 4 bytes per freq
+        // and position.
+        let encoded = bincode::serialize(&postings_list).unwrap();
+        let freqs = postings.iter().map(|(_, p)| p.len()).sum::<usize>();
+        assert!(encoded.len() < 2 * 1000 + freqs + 8 * 64);
+
+        // Truncated streams are reported rather than decoded as zeros.
+        let mut truncated = postings_list.clone();
+        let mut freqs = vec![];
+        truncated.doc_data = truncated
+            .doc_data
+            .slice(0..truncated.doc_data.len() - 1)
+            .unwrap();
+        truncated.position_data = truncated.position_data.slice(0..10).unwrap();
+        assert!(truncated.decode_block(6, &mut docs, &mut freqs).is_some());
+        assert!(truncated.decode_block(7, &mut docs, &mut freqs).is_none());
+        assert!(truncated.doc_ids().is_none());
+        truncated.decode_block(0, &mut docs, &mut freqs).unwrap();
+        assert!(truncated.decode_positions(0, &freqs, 0).is_some());
+        assert!(truncated.decode_positions(0, &freqs, 100).is_none());
+        assert!(truncated.decode_block_positions(0, &freqs).is_none());
+    }
+
+    #[test]
+    fn test_postings_list_decode_corrupted() {
+        let mut postings_list = PostingsList::new();
+        for doc_id in 1..=300 {
+            postings_list.add(doc_id * 2, vec![doc_id as u32], 10);
+        }
+        let decode = |postings_list: &PostingsList| {
+            let mut data = vec![];
+            postings_list.encode(&mut data);
+            PostingsList::decode(&Bytes::from(data))
+        };
+        let decoded = decode(&postings_list).unwrap();
+        assert_eq!(decoded.doc_ids(), postings_list.doc_ids());
+
+        // Headers that do not match their blocks are rejected.
+        let mut corrupted = postings_list.clone();
+        corrupted.len = 1000;
+        assert!(decode(&corrupted).is_none());
+        let mut corrupted = postings_list.clone();
+        corrupted.last_doc = 1000;
+        assert!(decode(&corrupted).is_none());
+        let mut corrupted = postings_list.clone();
+        corrupted.blocks[1].last_doc = 2;
+        assert!(decode(&corrupted).is_none());
+        let mut corrupted = postings_list.clone();
+        corrupted.blocks[2].doc_offset = corrupted.doc_data.len();
+        assert!(decode(&corrupted).is_none());
+
+        // Postings beyond the last doc_id of their block, or overflowing, are rejected.
+        let mut corrupted = postings_list.clone();
+        corrupted.doc_data.to_mut()[0] = 0x7f;
+        let (mut docs, mut freqs) = (vec![], vec![]);
+        assert!(corrupted.decode_block(0, &mut docs, &mut freqs).is_none());
+        assert!(corrupted.decode_block(1, &mut docs, &mut freqs).is_some());
+        let mut corrupted = postings_list.clone();
+        corrupted.blocks[1].last_doc = usize::MAX - 1;
+        corrupted.blocks[2].last_doc = usize::MAX;
+        corrupted.last_doc = usize::MAX;
+        let corrupted = decode(&corrupted).unwrap();
+        assert!(corrupted.decode_block(1, &mut docs, &mut freqs).is_none());
+        assert!(corrupted.decode_block(2, &mut docs, &mut freqs).is_none());
+        assert!(corrupted.doc_ids().is_none());
     }
 }
@@ -1,5 +1,20 @@
 pub mod analyzer;
+pub mod bytes;
+pub mod codec;
+pub mod commit;
+pub mod error;
+pub mod format;
 pub mod index;
+pub mod live_docs;
+pub mod merge;
 pub mod query;
 pub mod query_parser;
+pub mod reader;
+pub mod schema;
 pub mod searcher;
+pub mod similarity;
+pub mod store;
+pub mod terms;
+
+#[cfg(test)]
+mod testing;
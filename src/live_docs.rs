@@ -0,0 +1,87 @@
+use serde::{Deserialize, Serialize};
+
+/// Which documents of an index have not been deleted, as a bitset indexed by doc_id.
+/// Deleting a document only clears its bit: its postings stay in the index, and are
+/// skipped at search time.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct LiveDocs {
+    bits: Vec<u64>, // bit doc_id % 64 of word doc_id / 64 is set if the document is live
+    max_doc_id: usize,
+    deleted: usize,
+}
+
+impl LiveDocs {
+    pub fn new() -> Self {
+        LiveDocs::default()
+    }
+
+    /// All the documents up to `max_doc_id` live.
+    pub fn with_max_doc_id(max_doc_id: usize) -> Self {
+        let mut live_docs = LiveDocs::new();
+        while live_docs.max_doc_id < max_doc_id {
+            live_docs.push();
+        }
+        live_docs
+    }
+
+    /// Adds a live document, with the next doc_id.
+    pub fn push(&mut self) {
+        self.max_doc_id += 1;
+        let (word, bit) = (self.max_doc_id / 64, self.max_doc_id % 64);
+        if word >= self.bits.len() {
+            self.bits.resize(word + 1, 0);
+        }
+        self.bits[word] |= 1 << bit;
+    }
+
+    pub fn is_live(&self, doc_id: usize) -> bool {
+        self.bits
+            .get(doc_id / 64)
+            .is_some_and(|word| word & (1 << (doc_id % 64)) != 0)
+    }
+
+    /// Marks a document as deleted. Returns `false` if it was not live.
+    pub fn delete(&mut self, doc_id: usize) -> bool {
+        if !self.is_live(doc_id) {
+            return false;
+        }
+        self.bits[doc_id / 64] &= !(1 << (doc_id % 64));
+        self.deleted += 1;
+        true
+    }
+
+    pub fn max_doc_id(&self) -> usize {
+        self.max_doc_id
+    }
+
+    /// Number of deleted documents.
+    pub fn deleted(&self) -> usize {
+        self.deleted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LiveDocs;
+
+    #[test]
+    fn test_live_docs() {
+        let mut live_docs = LiveDocs::with_max_doc_id(130);
+        assert!(!live_docs.is_live(0));
+        assert!(live_docs.is_live(1));
+        assert!(live_docs.is_live(64));
+        assert!(live_docs.is_live(130));
+        assert!(!live_docs.is_live(131));
+
+        assert!(live_docs.delete(64));
+        assert!(!live_docs.delete(64));
+        assert!(!live_docs.delete(131));
+        assert!(!live_docs.is_live(64));
+        assert!(live_docs.is_live(63) && live_docs.is_live(65));
+        assert_eq!(live_docs.deleted(), 1);
+
+        live_docs.push();
+        assert!(live_docs.is_live(131));
+        assert_eq!(live_docs.max_doc_id(), 131);
+    }
+}
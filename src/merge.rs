@@ -0,0 +1,177 @@
+/// The size of a committed segment, as seen by a `MergePolicy`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SegmentSize {
+    pub doc_count: usize,  // number of live documents
+    pub max_doc_id: usize, // number of documents, including deleted ones
+}
+
+impl SegmentSize {
+    /// Share of the documents of the segment that are deleted.
+    pub fn deleted_ratio(&self) -> f64 {
+        if self.max_doc_id == 0 {
+            0.0
+        } else {
+            (self.max_doc_id - self.doc_count) as f64 / self.max_doc_id as f64
+        }
+    }
+}
+
+/// Decides which segments an `IndexWriter` merges. Merging segments speeds up searches,
+/// which visit every segment, and reclaims the space of deleted documents.
+pub trait MergePolicy: std::fmt::Debug + Send + Sync {
+    /// Picks the merges to run among `segments`, the committed segments that are not
+    /// being merged. Each merge is a list of indexes into `segments`, whose segments are
+    /// replaced by a single one; a merge of one segment rewrites it without its deleted
+    /// documents. A segment must not be part of several merges.
+    fn find_merges(&self, segments: &[SegmentSize]) -> Vec<Vec<usize>>;
+}
+
+/// Never merges segments.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct NoMergePolicy;
+
+impl MergePolicy for NoMergePolicy {
+    fn find_merges(&self, _segments: &[SegmentSize]) -> Vec<Vec<usize>> {
+        vec![]
+    }
+}
+
+/// Groups segments into tiers of exponentially growing sizes, and merges the smallest
+/// segments of a tier once it holds `segments_per_tier` of them, which moves them to the
+/// next tier. The number of segments thus grows logarithmically with the number of
+/// documents, and each document is merged a logarithmic number of times.
+///
+/// Segments with more than `deletes_pct_allowed` percent of deleted documents are also
+/// rewritten on their own.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TieredMergePolicy {
+    segments_per_tier: usize, // number of segments of a tier that triggers a merge
+    floor_segment_docs: usize, // largest segment size of the first tier
+    deletes_pct_allowed: f64, // percent of deleted documents that triggers a rewrite
+}
+
+impl TieredMergePolicy {
+    /// Creates a policy. `segments_per_tier` is raised to at least 2 and
+    /// `floor_segment_docs` to at least 1, so that the tiers grow.
+    pub fn new(
+        segments_per_tier: usize,
+        floor_segment_docs: usize,
+        deletes_pct_allowed: f64,
+    ) -> Self {
+        TieredMergePolicy {
+            segments_per_tier: segments_per_tier.max(2),
+            floor_segment_docs: floor_segment_docs.max(1),
+            deletes_pct_allowed,
+        }
+    }
+
+    pub fn segments_per_tier(&self) -> usize {
+        self.segments_per_tier
+    }
+
+    pub fn floor_segment_docs(&self) -> usize {
+        self.floor_segment_docs
+    }
+
+    pub fn deletes_pct_allowed(&self) -> f64 {
+        self.deletes_pct_allowed
+    }
+
+    /// The tier of a segment of `doc_count` live documents: 0 up to `floor_segment_docs`,
+    /// then one more each time the size is multiplied by `segments_per_tier`.
+    fn tier(&self, doc_count: usize) -> usize {
+        let mut tier = 0;
+        let mut bound = self.floor_segment_docs;
+        while doc_count > bound {
+            bound = bound.saturating_mul(self.segments_per_tier);
+            tier += 1;
+        }
+        tier
+    }
+}
+
+impl Default for TieredMergePolicy {
+    fn default() -> Self {
+        TieredMergePolicy::new(10, 1000, 33.0)
+    }
+}
+
+impl MergePolicy for TieredMergePolicy {
+    fn find_merges(&self, segments: &[SegmentSize]) -> Vec<Vec<usize>> {
+        let mut tiers = Vec::<Vec<usize>>::new();
+        for (i, segment) in segments.iter().enumerate() {
+            let tier = self.tier(segment.doc_count);
+            if tier >= tiers.len() {
+                tiers.resize(tier + 1, vec![]);
+            }
+            tiers[tier].push(i);
+        }
+
+        let mut merges = vec![];
+        let mut merged = vec![false; segments.len()];
+        for mut tier in tiers {
+            tier.sort_by_key(|&i| segments[i].doc_count);
+            for merge in tier.chunks_exact(self.segments_per_tier) {
+                for &i in merge {
+                    merged[i] = true;
+                }
+                merges.push(merge.to_vec());
+            }
+        }
+        for (i, segment) in segments.iter().enumerate() {
+            if !merged[i] && segment.deleted_ratio() * 100.0 > self.deletes_pct_allowed {
+                merges.push(vec![i]);
+            }
+        }
+        merges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MergePolicy, NoMergePolicy, SegmentSize, TieredMergePolicy};
+
+    fn size(doc_count: usize) -> SegmentSize {
+        SegmentSize {
+            doc_count,
+            max_doc_id: doc_count,
+        }
+    }
+
+    #[test]
+    fn test_tiered_merge_policy() {
+        let policy = TieredMergePolicy::new(3, 10, 20.0);
+        assert_eq!(policy.tier(0), 0);
+        assert_eq!(policy.tier(10), 0);
+        assert_eq!(policy.tier(11), 1);
+        assert_eq!(policy.tier(30), 1);
+        assert_eq!(policy.tier(31), 2);
+
+        // Tiers that are not full.
+        let mut segments = vec![size(100), size(5), size(20), size(8)];
+        assert!(policy.find_merges(&segments).is_empty());
+
+        // The smallest segments of a full tier are merged.
+        segments.push(size(1));
+        segments.push(size(10));
+        assert_eq!(policy.find_merges(&segments), vec![vec![4, 1, 3]]);
+
+        // Segments with too many deleted documents are rewritten.
+        segments[0].doc_count = 70;
+        assert_eq!(policy.find_merges(&segments), vec![vec![4, 1, 3], vec![0]]);
+        segments[0].doc_count = 90;
+        assert_eq!(policy.find_merges(&segments), vec![vec![4, 1, 3]]);
+
+        assert!(NoMergePolicy.find_merges(&segments).is_empty());
+
+        // Tiers always grow.
+        let policy = TieredMergePolicy::new(0, 0, 20.0);
+        assert_eq!(policy.segments_per_tier(), 2);
+        assert_eq!(policy.floor_segment_docs(), 1);
+        assert_eq!(policy.tier(4), 2);
+        assert_eq!(
+            policy.find_merges(&[size(1), size(1), size(0)]),
+            vec![vec![2, 0]]
+        );
+    }
+}
@@ -2,10 +2,75 @@ pub mod boolean;
 pub mod phrase;
 pub mod term;
 
-use crate::{index::Index, searcher::SearchResult};
+use crate::index::Index;
+use crate::searcher::{ScoredDoc, SearchResult};
+use crate::similarity::{Bm25, CollectionStats, Similarity};
 
 pub trait Query: std::fmt::Debug {
-    fn execute(&self, index: &Index) -> Vec<SearchResult>;
+    /// Returns a scorer that iterates over the matching documents of a segment in
+    /// increasing doc_id order, or `None` if no document can match. Terms are weighed with
+    /// `stats`, the statistics of all the segments searched, and documents are scored with
+    /// `similarity` unless the query has been given a similarity of its own.
+    fn scorer<'a>(
+        &'a self,
+        index: &'a Index,
+        stats: &CollectionStats<'_>,
+        similarity: &'a dyn Similarity,
+    ) -> Option<Box<dyn Scorer + 'a>>;
+
+    /// Returns all matching live documents ordered by descending BM25 score.
+    fn execute(&self, index: &Index) -> Vec<SearchResult> {
+        self.execute_with(index, &Bm25::default())
+    }
+
+    /// Returns all matching live documents ordered by descending score under `similarity`.
+    fn execute_with(&self, index: &Index, similarity: &dyn Similarity) -> Vec<SearchResult> {
+        let mut results = Vec::new();
+        let stats = CollectionStats::new(std::slice::from_ref(index));
+        if let Some(mut scorer) = self.scorer(index, &stats, similarity) {
+            while let Some(doc_id) = scorer.doc() {
+                if index.is_live(doc_id) {
+                    results.push(ScoredDoc {
+                        doc_id,
+                        score: scorer.score(),
+                    });
+                }
+                scorer.next();
+            }
+        }
+        results.sort_by(|a, b| b.cmp(a));
+        results
+            .into_iter()
+            .map(|r| SearchResult {
+                doc_id: r.doc_id,
+                score: r.score,
+            })
+            .collect()
+    }
+}
+
+/// Document-at-a-time iterator over the documents matched by a query.
+pub trait Scorer {
+    /// The current document, or `None` once the scorer is exhausted.
+    fn doc(&self) -> Option<usize>;
+
+    /// Moves to the next matching document. Returns `false` once exhausted.
+    fn next(&mut self) -> bool;
+
+    /// Moves to the first matching document whose doc_id is `>= target`. Does nothing if
+    /// the current document already satisfies it. Returns `false` once exhausted.
+    fn advance(&mut self, target: usize) -> bool {
+        while let Some(doc_id) = self.doc() {
+            if doc_id >= target {
+                return true;
+            }
+            self.next();
+        }
+        false
+    }
+
+    /// The score of the current document.
+    fn score(&self) -> f64;
 }
 
 #[derive(Debug)]
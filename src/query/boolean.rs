@@ -1,22 +1,332 @@
-use super::{Occur, Query};
-use crate::{index::Index, searcher::SearchResult};
+use std::sync::Arc;
+
+use super::{Occur, Query, Scorer};
+use crate::index::Index;
+use crate::similarity::{CollectionStats, Similarity};
 
 #[derive(Debug)]
-#[allow(dead_code)]
 pub struct BooleanQuery {
-    quries: Vec<(Occur, Box<dyn Query>)>,
+    queries: Vec<(Occur, Box<dyn Query>)>,
+    minimum_should_match: usize,
+    similarity: Option<Arc<dyn Similarity>>,
 }
 
 impl BooleanQuery {
     pub fn new(queries: Vec<(Occur, Box<dyn Query>)>) -> Self {
-        Self { quries: queries }
+        Self {
+            queries,
+            minimum_should_match: 0,
+            similarity: None,
+        }
+    }
+
+    /// Scores the clauses with `similarity` instead of the one of the searcher, unless
+    /// they have been given a similarity of their own.
+    pub fn with_similarity(mut self, similarity: Arc<dyn Similarity>) -> Self {
+        self.similarity = Some(similarity);
+        self
+    }
+
+    /// Sets the number of `Occur::Should` clauses a document has to match. With the
+    /// default of 0, should clauses only contribute to the score when there is at least
+    /// one `Occur::Must` clause, and at least one of them must match otherwise.
+    pub fn with_minimum_should_match(mut self, minimum_should_match: usize) -> Self {
+        self.minimum_should_match = minimum_should_match;
+        self
     }
 }
 
 impl Query for BooleanQuery {
-    fn execute(&self, index: &Index) -> Vec<SearchResult> {
-        // TODO
-        let _ = index;
-        Vec::new()
+    fn scorer<'a>(
+        &'a self,
+        index: &'a Index,
+        stats: &CollectionStats<'_>,
+        similarity: &'a dyn Similarity,
+    ) -> Option<Box<dyn Scorer + 'a>> {
+        let similarity = self.similarity.as_deref().unwrap_or(similarity);
+        let mut must = Vec::new();
+        let mut should = Vec::new();
+        let mut must_not = Vec::new();
+        for (occur, query) in &self.queries {
+            let scorer = query.scorer(index, stats, similarity);
+            match occur {
+                // A required clause without any match makes the whole query empty.
+                Occur::Must => must.push(scorer?),
+                Occur::Should => should.extend(scorer),
+                Occur::MustNot => must_not.extend(scorer),
+            }
+        }
+
+        let minimum_should_match = if must.is_empty() {
+            self.minimum_should_match.max(1)
+        } else {
+            self.minimum_should_match
+        };
+        if should.len() < minimum_should_match {
+            return None;
+        }
+
+        let mut scorer = BooleanScorer {
+            must,
+            should,
+            must_not,
+            minimum_should_match,
+            doc: None,
+            score: 0.0,
+        };
+        if scorer.find_match(0) {
+            Some(Box::new(scorer))
+        } else {
+            None
+        }
+    }
+}
+
+/// Returns a scorer matching the documents matched by any of `scorers`, scored with the
+/// sum of the scores of the scorers matching them.
+pub(crate) fn disjunction<'a>(
+    mut scorers: Vec<Box<dyn Scorer + 'a>>,
+) -> Option<Box<dyn Scorer + 'a>> {
+    if scorers.len() <= 1 {
+        return scorers.pop();
+    }
+    let mut scorer = BooleanScorer {
+        must: vec![],
+        should: scorers,
+        must_not: vec![],
+        minimum_should_match: 1,
+        doc: None,
+        score: 0.0,
+    };
+    if scorer.find_match(0) {
+        Some(Box::new(scorer))
+    } else {
+        None
+    }
+}
+
+/// Combines the scorers of the clauses of a `BooleanQuery`. Required clauses are
+/// intersected by leapfrogging their scorers to the largest current doc_id, optional
+/// clauses are merged as a disjunction, and excluded clauses are advanced lazily to each
+/// candidate document.
+struct BooleanScorer<'a> {
+    must: Vec<Box<dyn Scorer + 'a>>,
+    should: Vec<Box<dyn Scorer + 'a>>,
+    must_not: Vec<Box<dyn Scorer + 'a>>,
+    minimum_should_match: usize,
+    doc: Option<usize>,
+    score: f64,
+}
+
+impl BooleanScorer<'_> {
+    /// Positions the scorer on the first matching document `>= target`.
+    fn find_match(&mut self, mut target: usize) -> bool {
+        loop {
+            let candidate = if self.must.is_empty() {
+                self.next_should_candidate(target)
+            } else {
+                self.next_must_candidate(target)
+            };
+            let doc_id = match candidate {
+                Some(doc_id) => doc_id,
+                None => {
+                    self.doc = None;
+                    return false;
+                }
+            };
+
+            let mut score = self.must.iter().map(|scorer| scorer.score()).sum::<f64>();
+            let mut matched = 0;
+            for scorer in self.should.iter_mut() {
+                if scorer.advance(doc_id) && scorer.doc() == Some(doc_id) {
+                    score += scorer.score();
+                    matched += 1;
+                }
+            }
+            let excluded = self
+                .must_not
+                .iter_mut()
+                .any(|scorer| scorer.advance(doc_id) && scorer.doc() == Some(doc_id));
+
+            if matched >= self.minimum_should_match && !excluded {
+                self.doc = Some(doc_id);
+                self.score = score;
+                return true;
+            }
+            target = doc_id + 1;
+        }
+    }
+
+    /// Leapfrogs the required scorers until all of them agree on a doc_id `>= target`.
+    fn next_must_candidate(&mut self, target: usize) -> Option<usize> {
+        let mut target = target;
+        loop {
+            let mut agreed = true;
+            for scorer in self.must.iter_mut() {
+                if !scorer.advance(target) {
+                    return None;
+                }
+                let doc_id = scorer.doc()?;
+                if doc_id != target {
+                    target = doc_id;
+                    agreed = false;
+                }
+            }
+            if agreed {
+                return Some(target);
+            }
+        }
+    }
+
+    /// Returns the smallest doc_id `>= target` among the optional scorers.
+    fn next_should_candidate(&mut self, target: usize) -> Option<usize> {
+        self.should
+            .iter_mut()
+            .filter_map(|scorer| {
+                if scorer.advance(target) {
+                    scorer.doc()
+                } else {
+                    None
+                }
+            })
+            .min()
+    }
+}
+
+impl Scorer for BooleanScorer<'_> {
+    fn doc(&self) -> Option<usize> {
+        self.doc
+    }
+
+    fn next(&mut self) -> bool {
+        match self.doc {
+            Some(doc_id) => self.find_match(doc_id + 1),
+            None => false,
+        }
+    }
+
+    fn advance(&mut self, target: usize) -> bool {
+        match self.doc {
+            Some(doc_id) if doc_id >= target => true,
+            Some(_) => self.find_match(target),
+            None => false,
+        }
+    }
+
+    fn score(&self) -> f64 {
+        self.score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BooleanQuery;
+    use crate::index::Index;
+    use crate::query::{term::TermQuery, Occur, Query};
+    use crate::query_parser::QueryParser;
+    use crate::searcher::SearchResult;
+
+    fn doc_ids(query: &dyn Query, index: &Index) -> Vec<usize> {
+        let mut doc_ids = query
+            .execute(index)
+            .iter()
+            .map(|r| r.doc_id)
+            .collect::<Vec<usize>>();
+        doc_ids.sort_unstable();
+        doc_ids
+    }
+
+    fn term(term: &str) -> Box<dyn Query> {
+        Box::new(TermQuery::new(term.to_string()))
+    }
+
+    #[test]
+    fn test_execute() {
+        let index = {
+            let mut index = Index::new();
+            index.add("a b c").unwrap();
+            index.add("a b").unwrap();
+            index.add("a c").unwrap();
+            index.add("b c d").unwrap();
+            index.add("d").unwrap();
+            index
+        };
+
+        // Should only: disjunction.
+        let query = QueryParser::parse("c d").unwrap();
+        assert_eq!(doc_ids(query.as_ref(), &index), vec![1, 3, 4, 5]);
+
+        // Must: conjunction.
+        let query = QueryParser::parse("+a +b").unwrap();
+        assert_eq!(doc_ids(query.as_ref(), &index), vec![1, 2]);
+
+        // Must with should: should clauses only add to the score.
+        let query = QueryParser::parse("+a c").unwrap();
+        let results = query.execute(&index);
+        assert_eq!(
+            results.iter().map(|r| r.doc_id).collect::<Vec<usize>>(),
+            vec![3, 1, 2]
+        );
+        let a = TermQuery::new("a".to_string()).execute(&index);
+        let c = TermQuery::new("c".to_string()).execute(&index);
+        let score = |results: &[SearchResult], doc_id| {
+            results.iter().find(|r| r.doc_id == doc_id).unwrap().score
+        };
+        assert_eq!(results[0].score, score(&a, 3) + score(&c, 3));
+        assert_eq!(results[2].score, score(&a, 2));
+
+        // MustNot: exclusion.
+        let query = QueryParser::parse("+a -c").unwrap();
+        assert_eq!(doc_ids(query.as_ref(), &index), vec![2]);
+        let query = QueryParser::parse("b -a").unwrap();
+        assert_eq!(doc_ids(query.as_ref(), &index), vec![4]);
+
+        // MustNot only matches nothing.
+        let query = QueryParser::parse("-a").unwrap();
+        assert!(query.execute(&index).is_empty());
+
+        // A required term without postings matches nothing.
+        let query = QueryParser::parse("+a +z").unwrap();
+        assert!(query.execute(&index).is_empty());
+    }
+
+    #[test]
+    fn test_minimum_should_match() {
+        let index = {
+            let mut index = Index::new();
+            index.add("a b c").unwrap();
+            index.add("a b").unwrap();
+            index.add("a c").unwrap();
+            index.add("b c d").unwrap();
+            index
+        };
+
+        let query = BooleanQuery::new(vec![
+            (Occur::Should, term("a")),
+            (Occur::Should, term("b")),
+            (Occur::Should, term("c")),
+        ])
+        .with_minimum_should_match(2);
+        assert_eq!(doc_ids(&query, &index), vec![1, 2, 3, 4]);
+
+        let query = BooleanQuery::new(vec![
+            (Occur::Should, term("a")),
+            (Occur::Should, term("b")),
+            (Occur::Should, term("c")),
+        ])
+        .with_minimum_should_match(3);
+        assert_eq!(doc_ids(&query, &index), vec![1]);
+
+        let query = BooleanQuery::new(vec![
+            (Occur::Must, term("c")),
+            (Occur::Should, term("a")),
+            (Occur::Should, term("d")),
+        ])
+        .with_minimum_should_match(1);
+        assert_eq!(doc_ids(&query, &index), vec![1, 3, 4]);
+
+        let query =
+            BooleanQuery::new(vec![(Occur::Should, term("a"))]).with_minimum_should_match(2);
+        assert!(query.execute(&index).is_empty());
     }
 }
@@ -1,22 +1,416 @@
-use super::Query;
-use crate::{index::Index, searcher::SearchResult};
+use std::collections::BTreeMap;
+use std::sync::Arc;
 
+use super::{Query, Scorer};
+use crate::analyzer::{Analyzer, Token};
+use crate::index::{FieldIndex, Index};
+use crate::searcher::Cursor;
+use crate::similarity::{CollectionStats, Similarity, TermStats};
+
+/// Matches the documents containing the terms next to each other, in order. The terms are
+/// analyzed with the analyzer of the index. Where the analyzer adds synonyms, any of them
+/// may appear in the phrase, including synonyms of several words.
 #[derive(Clone, Debug)]
-#[allow(dead_code)]
 pub struct PhraseQuery {
     terms: Vec<String>,
+    field: Option<String>, // searches the default field if unset
+    similarity: Option<Arc<dyn Similarity>>,
 }
 
 impl PhraseQuery {
     pub fn new(terms: Vec<String>) -> Self {
-        Self { terms: terms }
+        Self {
+            terms,
+            field: None,
+            similarity: None,
+        }
+    }
+
+    /// Searches `field` instead of the default field of the index.
+    pub fn with_field(mut self, field: &str) -> Self {
+        self.field = Some(field.to_string());
+        self
+    }
+
+    /// Scores this query with `similarity` instead of the one of the searcher.
+    pub fn with_similarity(mut self, similarity: Arc<dyn Similarity>) -> Self {
+        self.similarity = Some(similarity);
+        self
     }
 }
 
 impl Query for PhraseQuery {
-    fn execute(&self, index: &Index) -> Vec<SearchResult> {
-        // TODO
-        let _ = index;
-        Vec::new()
+    fn scorer<'a>(
+        &'a self,
+        index: &'a Index,
+        stats: &CollectionStats<'_>,
+        similarity: &'a dyn Similarity,
+    ) -> Option<Box<dyn Scorer + 'a>> {
+        let similarity = self.similarity.as_deref().unwrap_or(similarity);
+        let (entry, field) = index.resolve_field(self.field.as_deref())?;
+        let tokens = entry.analyzer().analyze(&self.terms.join(" "));
+        phrase_scorer(entry.name(), field, &tokens, stats, similarity)
+    }
+}
+
+/// Returns a scorer matching the documents in which the tokens appear at the same
+/// distances from each other as they do in `tokens`. Where several tokens are stacked at
+/// the same position, such as synonyms, any of them may appear there. A token at
+/// `position` is followed by the tokens at the next position from `position +
+/// position_length` on, so that a synonym of several words matches in place of the
+/// words. The terms are weighed with their statistics in the field `name` of all the
+/// segments.
+pub fn phrase_scorer<'a>(
+    name: &str,
+    field: &'a FieldIndex,
+    tokens: &[Token],
+    stats: &CollectionStats<'_>,
+    similarity: &'a dyn Similarity,
+) -> Option<Box<dyn Scorer + 'a>> {
+    let first = tokens.iter().map(|token| token.position).min()?;
+    let mut offsets = tokens
+        .iter()
+        .map(|token| token.position - first)
+        .collect::<Vec<u32>>();
+    offsets.sort_unstable();
+    offsets.dedup();
+
+    let mut keys = tokens
+        .iter()
+        .map(|token| {
+            let offset = token.position - first;
+            (offset, offset + token.position_length.max(1), &token.token)
+        })
+        .collect::<Vec<(u32, u32, &String)>>();
+    keys.sort_unstable();
+    keys.dedup();
+    // Tokens missing from the index can never match, but their positions still count.
+    let terms = keys
+        .into_iter()
+        .filter_map(|(offset, end, token)| {
+            let postings_list = field.get_postings_list(token)?;
+            Some(PhraseTerm {
+                cursor: Cursor::new(postings_list)?,
+                stats: stats.term_stats(name, token),
+                offset,
+                end,
+            })
+        })
+        .collect::<Vec<PhraseTerm>>();
+
+    // Every match has one of the terms covering each offset.
+    let cuts = offsets
+        .iter()
+        .map(|&offset| {
+            (0..terms.len())
+                .filter(|&i| terms[i].offset <= offset && offset < terms[i].end)
+                .collect::<Vec<usize>>()
+        })
+        .collect::<Vec<Vec<usize>>>();
+    if cuts.iter().any(Vec::is_empty) {
+        return None;
+    }
+
+    let mut scorer = PhraseScorer {
+        terms,
+        offsets,
+        cuts,
+        field,
+        similarity,
+        doc: None,
+        phrase_freq: 0,
+    };
+    if scorer.find_match(0) {
+        Some(Box::new(scorer))
+    } else {
+        None
+    }
+}
+
+/// A token of a phrase, and the postings of its term.
+struct PhraseTerm<'a> {
+    cursor: Cursor<'a>,
+    stats: TermStats,
+    offset: u32, // position of the token relative to the first one
+    end: u32,    // offset of the first position after the token
+}
+
+/// Intersects, for each offset of the phrase, the union of the postings lists of the terms
+/// covering it, and keeps the documents in which the terms appear along a path through
+/// the phrase, at their offsets from a common start. Each term of the best path is
+/// scored as if it occurred as many times as the phrase does, and the scores are summed.
+struct PhraseScorer<'a> {
+    terms: Vec<PhraseTerm<'a>>,
+    offsets: Vec<u32>,     // distinct offsets of the tokens, in increasing order
+    cuts: Vec<Vec<usize>>, // indices of the terms covering each offset
+    field: &'a FieldIndex,
+    similarity: &'a dyn Similarity,
+    doc: Option<usize>,
+    phrase_freq: u32,
+}
+
+impl PhraseScorer<'_> {
+    /// Positions the scorer on the first document `>= target` containing the phrase.
+    fn find_match(&mut self, mut target: usize) -> bool {
+        loop {
+            let doc_id = match self.next_candidate(target) {
+                Some(doc_id) => doc_id,
+                None => {
+                    self.doc = None;
+                    return false;
+                }
+            };
+            let positions = self.positions(doc_id);
+            let phrase_freq = self
+                .starts(&positions)
+                .into_iter()
+                .filter(|&start| self.best_path(&positions, start, &|_| 0.0).is_some())
+                .count() as u32;
+            if phrase_freq > 0 {
+                self.doc = Some(doc_id);
+                self.phrase_freq = phrase_freq;
+                return true;
+            }
+            target = doc_id + 1;
+        }
+    }
+
+    /// Leapfrogs the cursors until every offset has a term in the same doc_id
+    /// `>= target`.
+    fn next_candidate(&mut self, mut target: usize) -> Option<usize> {
+        loop {
+            let mut candidate = target;
+            let terms = &mut self.terms;
+            for cut in self.cuts.iter() {
+                let doc_id = cut
+                    .iter()
+                    .filter_map(|&i| {
+                        let cursor = &mut terms[i].cursor;
+                        cursor.advance(target);
+                        cursor.next_doc
+                    })
+                    .min()?;
+                candidate = candidate.max(doc_id);
+            }
+            if candidate == target {
+                return Some(target);
+            }
+            target = candidate;
+        }
+    }
+
+    /// The positions of each term in `doc_id`, empty for the terms it does not contain.
+    fn positions(&self, doc_id: usize) -> Vec<Vec<u32>> {
+        self.terms
+            .iter()
+            .map(|term| match term.cursor.next_doc {
+                Some(next_doc) if next_doc == doc_id => term.cursor.positions(),
+                _ => vec![],
+            })
+            .collect()
+    }
+
+    /// The positions at which a match may start, those of the terms at offset 0.
+    fn starts(&self, positions: &[Vec<u32>]) -> Vec<u32> {
+        let mut starts = self.cuts[0]
+            .iter()
+            .flat_map(|&i| positions[i].iter().copied())
+            .collect::<Vec<u32>>();
+        starts.sort_unstable();
+        starts.dedup();
+        starts
+    }
+
+    /// The largest sum of the weights of the terms along a path through the phrase whose
+    /// terms all appear at `start` plus their offsets, or `None` if there is no such path.
+    fn best_path(
+        &self,
+        positions: &[Vec<u32>],
+        start: u32,
+        weight: &dyn Fn(usize) -> f64,
+    ) -> Option<f64> {
+        // Best weight of the paths reaching each offset, visited in increasing order.
+        let mut reached = BTreeMap::new();
+        reached.insert(0, 0.0);
+        let mut best: Option<f64> = None;
+        while let Some((from, path_weight)) = reached.pop_first() {
+            let offset = match self.offsets.iter().find(|&&offset| offset >= from) {
+                Some(&offset) => offset,
+                None => {
+                    best = Some(best.map_or(path_weight, |best| best.max(path_weight)));
+                    continue;
+                }
+            };
+            for (i, term) in self.terms.iter().enumerate() {
+                if term.offset == offset && positions[i].binary_search(&(start + offset)).is_ok() {
+                    let reached_weight = reached.entry(term.end).or_insert(f64::NEG_INFINITY);
+                    *reached_weight = reached_weight.max(path_weight + weight(i));
+                }
+            }
+        }
+        best
+    }
+}
+
+impl Scorer for PhraseScorer<'_> {
+    fn doc(&self) -> Option<usize> {
+        self.doc
+    }
+
+    fn next(&mut self) -> bool {
+        match self.doc {
+            Some(doc_id) => self.find_match(doc_id + 1),
+            None => false,
+        }
+    }
+
+    fn advance(&mut self, target: usize) -> bool {
+        match self.doc {
+            Some(doc_id) if doc_id >= target => true,
+            Some(_) => self.find_match(target),
+            None => false,
+        }
+    }
+
+    fn score(&self) -> f64 {
+        let doc_id = match self.doc {
+            Some(doc_id) => doc_id,
+            None => return 0.0,
+        };
+        let doc_length = self.field.doc_length(doc_id);
+        let weight = |i: usize| {
+            self.similarity
+                .score(&self.terms[i].stats, self.phrase_freq, doc_length)
+        };
+        let positions = self.positions(doc_id);
+        self.starts(&positions)
+            .into_iter()
+            .filter_map(|start| self.best_path(&positions, start, &weight))
+            .fold(0.0, f64::max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PhraseQuery;
+    use crate::analyzer::synonym::SynonymMap;
+    use crate::analyzer::{AnalyzerConfig, TokenFilterConfig, TokenizerConfig};
+    use crate::index::Index;
+    use crate::query::Query;
+    use crate::query_parser::QueryParser;
+
+    fn phrase(terms: &[&str]) -> PhraseQuery {
+        PhraseQuery::new(terms.iter().map(|t| t.to_string()).collect())
+    }
+
+    #[test]
+    fn test_execute() {
+        let index = {
+            let mut index = Index::new();
+            index.add("new york city").unwrap();
+            index.add("york new").unwrap();
+            index.add("new york new york").unwrap();
+            index.add("new jersey and york").unwrap();
+            index
+        };
+
+        let results = phrase(&["new", "york"]).execute(&index);
+        assert_eq!(
+            results.iter().map(|r| r.doc_id).collect::<Vec<usize>>(),
+            vec![3, 1]
+        );
+        assert!(results[0].score > results[1].score);
+
+        let results = phrase(&["new", "york", "city"]).execute(&index);
+        assert_eq!(
+            results.iter().map(|r| r.doc_id).collect::<Vec<usize>>(),
+            vec![1]
+        );
+
+        let results = phrase(&["york", "new"]).execute(&index);
+        assert_eq!(
+            results.iter().map(|r| r.doc_id).collect::<Vec<usize>>(),
+            vec![2, 3]
+        );
+
+        assert!(phrase(&["jersey", "york"]).execute(&index).is_empty());
+        assert!(phrase(&["new", "boston"]).execute(&index).is_empty());
+        assert!(phrase(&[]).execute(&index).is_empty());
+
+        let results = QueryParser::parse("\"york city\"").unwrap().execute(&index);
+        assert_eq!(
+            results.iter().map(|r| r.doc_id).collect::<Vec<usize>>(),
+            vec![1]
+        );
+
+        let results = QueryParser::parse("+\"new york\" -city")
+            .unwrap()
+            .execute(&index);
+        assert_eq!(
+            results.iter().map(|r| r.doc_id).collect::<Vec<usize>>(),
+            vec![3]
+        );
+    }
+
+    #[test]
+    fn test_execute_synonyms() {
+        let synonyms = SynonymMap::parse("tv, television\nnyc, new york city").unwrap();
+        let index = {
+            let mut index = Index::with_analyzer(AnalyzerConfig::new(
+                TokenizerConfig::Whitespace,
+                vec![TokenFilterConfig::Synonyms(synonyms)],
+            ));
+            index.add("i love nyc").unwrap();
+            index.add("new york city is big").unwrap();
+            index.add("watch television").unwrap();
+            index.add("watch tv at home").unwrap();
+            index.add("new jersey city is big").unwrap();
+            index
+        };
+        let doc_ids = |terms: &[&str]| {
+            let mut doc_ids = phrase(terms)
+                .execute(&index)
+                .iter()
+                .map(|r| r.doc_id)
+                .collect::<Vec<usize>>();
+            doc_ids.sort_unstable();
+            doc_ids
+        };
+
+        assert_eq!(doc_ids(&["watch", "tv"]), vec![3, 4]);
+        assert_eq!(doc_ids(&["watch", "television"]), vec![3, 4]);
+        // Multi-word synonyms match in place of each other, in the middle of a phrase.
+        assert_eq!(doc_ids(&["nyc", "is", "big"]), vec![2]);
+        assert_eq!(doc_ids(&["love", "new", "york", "city"]), vec![1]);
+        assert_eq!(doc_ids(&["city", "is", "big"]), vec![2, 5]);
+        assert!(doc_ids(&["love", "new", "york", "is"]).is_empty());
+    }
+
+    #[test]
+    fn test_execute_many_synonyms() {
+        // Each word has a synonym, so there are 2^24 ways to read the phrase.
+        let words = (0..24).map(|i| format!("w{}", i)).collect::<Vec<String>>();
+        let rules = words
+            .iter()
+            .map(|word| format!("{}, {}x", word, word))
+            .collect::<Vec<String>>()
+            .join("\n");
+        let index = {
+            let mut index = Index::with_analyzer(AnalyzerConfig::new(
+                TokenizerConfig::Whitespace,
+                vec![TokenFilterConfig::Synonyms(
+                    SynonymMap::parse(&rules).unwrap(),
+                )],
+            ));
+            index.add(&words.join(" ")).unwrap();
+            index.add(&words.join("x ")).unwrap();
+            index.add(&words[1..].join(" ")).unwrap();
+            index
+        };
+
+        let results = PhraseQuery::new(words.clone()).execute(&index);
+        let mut doc_ids = results.iter().map(|r| r.doc_id).collect::<Vec<usize>>();
+        doc_ids.sort_unstable();
+        assert_eq!(doc_ids, vec![1, 2]);
     }
 }
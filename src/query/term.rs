@@ -1,22 +1,248 @@
-use super::Query;
-use crate::{index::Index, searcher::SearchResult};
+use std::sync::Arc;
 
+use super::boolean::disjunction;
+use super::phrase::phrase_scorer;
+use super::{Query, Scorer};
+use crate::analyzer::Analyzer;
+use crate::index::{FieldIndex, Index};
+use crate::searcher::Cursor;
+use crate::similarity::{CollectionStats, Similarity, TermStats};
+
+/// Matches the documents containing a term. The term is analyzed with the analyzer of the
+/// index, and matched as a phrase if the analyzer splits it into several tokens. If the
+/// analyzer adds synonyms, the query matches any of them.
 #[derive(Clone, Debug)]
-#[allow(dead_code)]
 pub struct TermQuery {
     term: String,
+    field: Option<String>, // searches the default field if unset
+    similarity: Option<Arc<dyn Similarity>>,
 }
 
 impl TermQuery {
     pub fn new(term: String) -> Self {
-        Self { term: term }
+        Self {
+            term,
+            field: None,
+            similarity: None,
+        }
+    }
+
+    /// Searches `field` instead of the default field of the index.
+    pub fn with_field(mut self, field: &str) -> Self {
+        self.field = Some(field.to_string());
+        self
+    }
+
+    /// Scores this query with `similarity` instead of the one of the searcher.
+    pub fn with_similarity(mut self, similarity: Arc<dyn Similarity>) -> Self {
+        self.similarity = Some(similarity);
+        self
     }
 }
 
 impl Query for TermQuery {
-    fn execute(&self, index: &Index) -> Vec<SearchResult> {
-        // TODO
-        let _ = index;
-        Vec::new()
+    fn scorer<'a>(
+        &'a self,
+        index: &'a Index,
+        stats: &CollectionStats<'_>,
+        similarity: &'a dyn Similarity,
+    ) -> Option<Box<dyn Scorer + 'a>> {
+        let similarity = self.similarity.as_deref().unwrap_or(similarity);
+        let (entry, field) = index.resolve_field(self.field.as_deref())?;
+        let tokens = entry.analyzer().analyze(&self.term);
+        let single_word = tokens
+            .iter()
+            .all(|token| token.position == tokens[0].position && token.position_length <= 1);
+        if !single_word {
+            return phrase_scorer(entry.name(), field, &tokens, stats, similarity);
+        }
+        // A single word, or synonyms of a single word.
+        let mut terms = tokens
+            .iter()
+            .map(|token| token.token.as_str())
+            .collect::<Vec<&str>>();
+        terms.sort_unstable();
+        terms.dedup();
+        let scorers = terms
+            .into_iter()
+            .filter_map(|term| {
+                let stats = stats.term_stats(entry.name(), term);
+                term_scorer(field, term, stats, similarity)
+            })
+            .collect();
+        disjunction(scorers)
+    }
+}
+
+/// Returns a scorer over the postings list of an analyzed term, weighed with `stats`.
+fn term_scorer<'a>(
+    field: &'a FieldIndex,
+    term: &str,
+    stats: TermStats,
+    similarity: &'a dyn Similarity,
+) -> Option<Box<dyn Scorer + 'a>> {
+    let postings_list = field.get_postings_list(term)?;
+    let cursor = Cursor::new(postings_list)?;
+    Some(Box::new(TermScorer {
+        cursor,
+        field,
+        stats,
+        similarity,
+    }))
+}
+
+/// Walks a single postings list and scores each document with a similarity.
+pub struct TermScorer<'a> {
+    cursor: Cursor<'a>,
+    field: &'a FieldIndex,
+    stats: TermStats,
+    similarity: &'a dyn Similarity,
+}
+
+impl Scorer for TermScorer<'_> {
+    fn doc(&self) -> Option<usize> {
+        self.cursor.next_doc
+    }
+
+    fn next(&mut self) -> bool {
+        self.cursor.next()
+    }
+
+    fn advance(&mut self, target: usize) -> bool {
+        self.cursor.advance(target)
+    }
+
+    fn score(&self) -> f64 {
+        let doc_length = self
+            .cursor
+            .next_doc
+            .map_or(0, |doc_id| self.field.doc_length(doc_id));
+        self.similarity
+            .score(&self.stats, self.cursor.freq(), doc_length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::TermQuery;
+    use crate::analyzer::synonym::SynonymMap;
+    use crate::analyzer::{AnalyzerConfig, TokenFilterConfig, TokenizerConfig};
+    use crate::index::Index;
+    use crate::query::Query;
+    use crate::query_parser::QueryParser;
+    use crate::similarity::{Bm25, Similarity, TermStats, TfIdf};
+
+    #[test]
+    fn test_execute() {
+        let index = {
+            let mut index = Index::new();
+            index.add("two one two").unwrap();
+            index.add("one two three two three three").unwrap();
+            index.add("three").unwrap();
+            index
+        };
+
+        let stats = TermStats {
+            doc_freq: 2,
+            collection_freq: 4,
+            doc_count: 3,
+            total_length: 10,
+            avg_doc_length: 10.0 / 3.0,
+        };
+
+        // The short document wins under BM25 length normalization.
+        let results = TermQuery::new("three".to_string()).execute(&index);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].doc_id, 3);
+        assert_eq!(results[0].score, Bm25::default().score(&stats, 1, 1));
+        assert_eq!(results[1].doc_id, 2);
+        assert_eq!(results[1].score, Bm25::default().score(&stats, 3, 6));
+
+        // The similarity can be chosen by the caller or by the query itself.
+        let results = TermQuery::new("three".to_string()).execute_with(&index, &TfIdf);
+        assert_eq!(results[0].score, TfIdf.score(&stats, 1, 1));
+        let results = TermQuery::new("three".to_string())
+            .with_similarity(Arc::new(TfIdf))
+            .execute(&index);
+        assert_eq!(results[0].score, TfIdf.score(&stats, 1, 1));
+
+        let results = TermQuery::new("four".to_string()).execute(&index);
+        assert!(results.is_empty());
+
+        let results = QueryParser::parse("one").unwrap().execute(&index);
+        assert_eq!(
+            results.iter().map(|r| r.doc_id).collect::<Vec<usize>>(),
+            vec![1, 2]
+        );
+    }
+
+    #[test]
+    fn test_execute_analyzed() {
+        let index = {
+            let mut index = Index::with_analyzer(AnalyzerConfig::new(
+                TokenizerConfig::Whitespace,
+                vec![
+                    TokenFilterConfig::StripPunctuation,
+                    TokenFilterConfig::Lowercase,
+                    TokenFilterConfig::english_stop_words(),
+                ],
+            ));
+            index.add("Hello, World!").unwrap();
+            index.add("the world of hello").unwrap();
+            index
+        };
+
+        let doc_ids = |query: &dyn Query| {
+            query
+                .execute(&index)
+                .iter()
+                .map(|r| r.doc_id)
+                .collect::<Vec<usize>>()
+        };
+        assert_eq!(doc_ids(&TermQuery::new("HELLO".to_string())), vec![1, 2]);
+        assert_eq!(doc_ids(&TermQuery::new("world.".to_string())), vec![1, 2]);
+        assert!(doc_ids(&TermQuery::new("the".to_string())).is_empty());
+        // Several tokens are matched as a phrase.
+        assert_eq!(doc_ids(&TermQuery::new("hello world".to_string())), vec![1]);
+        // Removed stop words still count as a position.
+        assert_eq!(
+            doc_ids(&TermQuery::new("world in hello".to_string())),
+            vec![2]
+        );
+        assert!(doc_ids(&TermQuery::new("world hello".to_string())).is_empty());
+    }
+
+    #[test]
+    fn test_execute_synonyms() {
+        let synonyms = SynonymMap::parse("tv, television\nnyc, new york city").unwrap();
+        let index = {
+            let mut index = Index::with_analyzer(AnalyzerConfig::new(
+                TokenizerConfig::Whitespace,
+                vec![TokenFilterConfig::Synonyms(synonyms)],
+            ));
+            index.add("i love nyc").unwrap();
+            index.add("new york city is big").unwrap();
+            index.add("watch television").unwrap();
+            index.add("new jersey city").unwrap();
+            index.add("tv and television").unwrap();
+            index
+        };
+        let doc_ids = |term: &str| {
+            let mut doc_ids = TermQuery::new(term.to_string())
+                .execute(&index)
+                .iter()
+                .map(|r| r.doc_id)
+                .collect::<Vec<usize>>();
+            doc_ids.sort_unstable();
+            doc_ids
+        };
+
+        assert_eq!(doc_ids("tv"), vec![3, 5]);
+        assert_eq!(doc_ids("television"), vec![3, 5]);
+        assert_eq!(doc_ids("nyc"), vec![1, 2]);
+        assert_eq!(doc_ids("new york city"), vec![1, 2]);
+        assert_eq!(doc_ids("city"), vec![1, 2, 4]);
     }
 }
@@ -25,7 +25,7 @@ impl QueryParser {
                 Self::simple_phrase_query,
                 Self::boolean_query,
             )),
-            |q| Ok::<Box<dyn Query>, VerboseError<&str>>(q),
+            Ok::<Box<dyn Query>, VerboseError<&str>>,
         )(query)
     }
 
@@ -52,26 +52,13 @@ impl QueryParser {
             permutation((opt(alt((char('+'), char('-')))), Self::subquery)),
             |(occur, query)| match occur {
                 Some(op) => match op {
-                    '+' => {
-                        return Ok::<(Occur, Box<dyn Query>), VerboseError<&str>>((
-                            Occur::Must,
-                            query,
-                        ))
-                    }
+                    '+' => Ok::<(Occur, Box<dyn Query>), VerboseError<&str>>((Occur::Must, query)),
                     '-' => {
-                        return Ok::<(Occur, Box<dyn Query>), VerboseError<&str>>((
-                            Occur::MustNot,
-                            query,
-                        ))
+                        Ok::<(Occur, Box<dyn Query>), VerboseError<&str>>((Occur::MustNot, query))
                     }
-                    _ => return Err(VerboseError { errors: vec![] }),
+                    _ => Err(VerboseError { errors: vec![] }),
                 },
-                None => {
-                    return Ok::<(Occur, Box<dyn Query>), VerboseError<&str>>((
-                        Occur::Should,
-                        query,
-                    ))
-                }
+                None => Ok::<(Occur, Box<dyn Query>), VerboseError<&str>>((Occur::Should, query)),
             },
         )(query)
     }
@@ -117,7 +104,7 @@ mod tests {
     #[test]
     fn test_term_query() {
         assert_eq!(
-            format!("{:?}", QueryParser::term_query(&"abc").unwrap().1),
+            format!("{:?}", QueryParser::term_query("abc").unwrap().1),
             format!("{:?}", TermQuery::new("abc".to_string()))
         );
     }
@@ -125,11 +112,11 @@ mod tests {
     #[test]
     fn test_phrase_query() {
         assert_eq!(
-            format!("{:?}", QueryParser::phrase_query(&"\"abc\"").unwrap().1),
+            format!("{:?}", QueryParser::phrase_query("\"abc\"").unwrap().1),
             format!("{:?}", PhraseQuery::new(vec!["abc".to_string()]))
         );
         assert_eq!(
-            format!("{:?}", QueryParser::phrase_query(&"\"abc def\"").unwrap().1),
+            format!("{:?}", QueryParser::phrase_query("\"abc def\"").unwrap().1),
             format!(
                 "{:?}",
                 PhraseQuery::new(vec!["abc".to_string(), "def".to_string()])
@@ -141,24 +128,21 @@ mod tests {
     fn test_boolean_term() {
         // TermQuery
         assert_eq!(
-            format!("{:?}", QueryParser::_boolean_term(&"abc").unwrap().1),
+            format!("{:?}", QueryParser::_boolean_term("abc").unwrap().1),
             format!("{:?}", (Occur::Should, TermQuery::new("abc".to_string())))
         );
         assert_eq!(
-            format!("{:?}", QueryParser::_boolean_term(&"+abc").unwrap().1),
+            format!("{:?}", QueryParser::_boolean_term("+abc").unwrap().1),
             format!("{:?}", (Occur::Must, TermQuery::new("abc".to_string())))
         );
         assert_eq!(
-            format!("{:?}", QueryParser::_boolean_term(&"-abc").unwrap().1),
+            format!("{:?}", QueryParser::_boolean_term("-abc").unwrap().1),
             format!("{:?}", (Occur::MustNot, TermQuery::new("abc".to_string())))
         );
 
         // PhraseQuery
         assert_eq!(
-            format!(
-                "{:?}",
-                QueryParser::_boolean_term(&"\"abc def\"").unwrap().1
-            ),
+            format!("{:?}", QueryParser::_boolean_term("\"abc def\"").unwrap().1),
             format!(
                 "{:?}",
                 (
@@ -168,14 +152,14 @@ mod tests {
             )
         );
         assert_eq!(
-            format!("{:?}", QueryParser::_boolean_term(&"+\"abc\"").unwrap().1),
+            format!("{:?}", QueryParser::_boolean_term("+\"abc\"").unwrap().1),
             format!(
                 "{:?}",
                 (Occur::Must, PhraseQuery::new(vec!["abc".to_string()]))
             )
         );
         assert_eq!(
-            format!("{:?}", QueryParser::_boolean_term(&"-\"abc\"").unwrap().1),
+            format!("{:?}", QueryParser::_boolean_term("-\"abc\"").unwrap().1),
             format!(
                 "{:?}",
                 (Occur::MustNot, PhraseQuery::new(vec!["abc".to_string()]))
@@ -186,7 +170,7 @@ mod tests {
     #[test]
     fn test_boolean_query() {
         assert_eq!(
-            format!("{:?}", QueryParser::boolean_query(&"abc def").unwrap().1),
+            format!("{:?}", QueryParser::boolean_query("abc def").unwrap().1),
             format!(
                 "{:?}",
                 BooleanQuery::new(vec![
@@ -197,7 +181,7 @@ mod tests {
         );
 
         assert_eq!(
-            format!("{:?}", QueryParser::boolean_query(&"+abc +def").unwrap().1),
+            format!("{:?}", QueryParser::boolean_query("+abc +def").unwrap().1),
             format!(
                 "{:?}",
                 BooleanQuery::new(vec![
@@ -210,9 +194,7 @@ mod tests {
         assert_eq!(
             format!(
                 "{:?}",
-                QueryParser::boolean_query(&"+abc def -g +\"hi\"")
-                    .unwrap()
-                    .1
+                QueryParser::boolean_query("+abc def -g +\"hi\"").unwrap().1
             ),
             format!(
                 "{:?}",
@@ -233,17 +215,17 @@ mod tests {
     fn test_parse() {
         // TermQuery
         assert_eq!(
-            format!("{:?}", QueryParser::parse(&"abc").unwrap()),
+            format!("{:?}", QueryParser::parse("abc").unwrap()),
             format!("{:?}", TermQuery::new("abc".to_string()))
         );
         // PhraseQuery
         assert_eq!(
-            format!("{:?}", QueryParser::parse(&"\"abc\"").unwrap()),
+            format!("{:?}", QueryParser::parse("\"abc\"").unwrap()),
             format!("{:?}", PhraseQuery::new(vec!["abc".to_string()]))
         );
         // BooleanQuery
         assert_eq!(
-            format!("{:?}", QueryParser::parse(&"abc def").unwrap()),
+            format!("{:?}", QueryParser::parse("abc def").unwrap()),
             format!(
                 "{:?}",
                 BooleanQuery::new(vec![
@@ -0,0 +1,187 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::commit::{Commit, Manifest};
+use crate::error::IndexError;
+use crate::searcher::Searcher;
+
+/// Reads the index in a directory, as of its last commit when the reader was opened or
+/// last reloaded. Searchers handed out by the reader keep the commit they were created
+/// on, so that a search sees the same documents from start to end, and they can be sent
+/// to other threads.
+#[derive(Debug)]
+pub struct IndexReader {
+    directory_path: PathBuf,
+    commit: Arc<Commit>,
+}
+
+impl IndexReader {
+    /// Opens the index in `path`. See `Commit::open` for the errors.
+    pub fn open(path: &Path) -> Result<Self, IndexError> {
+        Ok(IndexReader {
+            directory_path: path.to_path_buf(),
+            commit: Arc::new(Commit::open(path)?),
+        })
+    }
+
+    /// Moves the reader to the last commit of the index, if there was a commit since it
+    /// was opened or last reloaded. Returns whether it moved. Searchers created before
+    /// keep searching the previous commit.
+    pub fn reload(&mut self) -> Result<bool, IndexError> {
+        if Manifest::open(&self.directory_path)?.generation() == self.commit.generation() {
+            return Ok(false);
+        }
+        self.commit = Arc::new(Commit::open(&self.directory_path)?);
+        Ok(true)
+    }
+
+    /// The commit the reader is on.
+    pub fn commit(&self) -> &Arc<Commit> {
+        &self.commit
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.commit.generation()
+    }
+
+    /// Number of live documents.
+    pub fn doc_count(&self) -> usize {
+        self.commit.doc_count()
+    }
+
+    /// A searcher over the commit the reader is on.
+    pub fn searcher(&self) -> Searcher<'static> {
+        Searcher::with_commit(Arc::clone(&self.commit))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IndexReader;
+    use crate::error::IndexError;
+    use crate::format::FORMAT_VERSION;
+    use crate::index::IndexWriter;
+    use crate::schema::{Document, FieldEntry, Schema};
+    use crate::testing::TempDir;
+
+    #[test]
+    fn test_reader() {
+        let directory = TempDir::new("reader");
+        let directory_path = directory.path();
+        assert!(matches!(
+            IndexReader::open(directory_path),
+            Err(IndexError::NotFound(_))
+        ));
+
+        let schema = Schema::new()
+            .with_field(FieldEntry::new("id").with_indexed(false).with_stored(true))
+            .with_field(FieldEntry::new("title"))
+            .with_unique_key("id");
+        let document = |id: &str| {
+            Document::new()
+                .with_value("id", id)
+                .with_value("title", "sheltie")
+        };
+        let mut writer = IndexWriter::with_schema(directory_path, schema).unwrap();
+        writer.add(&document("a")).unwrap();
+        writer.commit().unwrap();
+
+        let mut reader = IndexReader::open(directory_path).unwrap();
+        assert_eq!(reader.generation(), 1);
+        assert!(!reader.reload().unwrap());
+        let searcher = reader.searcher();
+
+        writer.add(&document("b")).unwrap();
+        writer.commit().unwrap();
+        assert_eq!(reader.doc_count(), 1);
+        assert!(reader.reload().unwrap());
+        assert_eq!(reader.generation(), 2);
+        assert_eq!(reader.doc_count(), 2);
+
+        // Searchers keep the commit they were created on.
+        let handle = std::thread::spawn(move || searcher.search("sheltie", 10).len());
+        assert_eq!(handle.join().unwrap(), 1);
+        assert_eq!(reader.searcher().search("sheltie", 10).len(), 2);
+        drop(writer);
+
+        // Invalid and missing files are reported as corrupted.
+        std::fs::write(directory_path.join("_1.store"), b"not a store").unwrap();
+        let err = IndexReader::open(directory_path).unwrap_err();
+        assert!(
+            matches!(&err, IndexError::Corrupted { path, .. } if path.ends_with("_1.store")),
+            "{}",
+            err
+        );
+        std::fs::remove_file(directory_path.join("_1.store")).unwrap();
+        let err = IndexReader::open(directory_path).unwrap_err();
+        assert!(matches!(err, IndexError::Corrupted { .. }), "{}", err);
+        std::fs::write(directory_path.join("manifest"), b"").unwrap();
+        let err = IndexReader::open(directory_path).unwrap_err();
+        // A writer does not replace an index it cannot read, whose segment names it
+        // could reuse.
+        let writer_err = IndexWriter::new(directory_path).err().unwrap();
+        assert!(matches!(err, IndexError::Corrupted { .. }), "{}", err);
+        assert_eq!(writer_err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_reader_format_errors() {
+        let directory = TempDir::new("format");
+        let directory_path = directory.path();
+        let mut writer = IndexWriter::new(directory_path).unwrap();
+        writer
+            .add(&Document::new().with_value("text", "sheltie"))
+            .unwrap();
+        writer.commit().unwrap();
+        drop(writer);
+        let reader = IndexReader::open(directory_path).unwrap();
+        assert!(reader.commit().check_integrity().is_ok());
+
+        // Files are replaced, as a writer would, so that open readers keep their view.
+        let modify = |file: &str, f: &dyn Fn(&mut Vec<u8>)| {
+            let path = directory_path.join(file);
+            let mut data = std::fs::read(&path).unwrap();
+            f(&mut data);
+            std::fs::remove_file(&path).unwrap();
+            std::fs::write(&path, data).unwrap();
+        };
+        let flip_last = |data: &mut Vec<u8>| *data.last_mut().unwrap() ^= 1;
+
+        // Postings and stored fields files are only mapped when opened, and verified on
+        // demand.
+        for file in ["_0.post", "_0.store"] {
+            modify(file, &flip_last);
+            let reader = IndexReader::open(directory_path).unwrap();
+            let err = reader.commit().check_integrity().unwrap_err();
+            assert!(
+                matches!(&err, IndexError::ChecksumMismatch { path, .. } if path.ends_with(file)),
+                "{}",
+                err
+            );
+            modify(file, &flip_last);
+        }
+
+        modify("_0.doc", &flip_last);
+        let err = IndexReader::open(directory_path).unwrap_err();
+        assert!(
+            matches!(&err, IndexError::ChecksumMismatch { path, .. } if path.ends_with("_0.doc")),
+            "{}",
+            err
+        );
+        modify("_0.doc", &flip_last);
+
+        let set_version = |version: u32| {
+            move |data: &mut Vec<u8>| data[4..8].copy_from_slice(&version.to_le_bytes())
+        };
+        modify("manifest", &set_version(FORMAT_VERSION + 1));
+        let err = IndexReader::open(directory_path).unwrap_err();
+        modify("manifest", &set_version(FORMAT_VERSION));
+        let reader = IndexReader::open(directory_path);
+        assert!(
+            matches!(err, IndexError::UnsupportedVersion { version, .. } if version == FORMAT_VERSION + 1),
+            "{}",
+            err
+        );
+        assert_eq!(reader.unwrap().doc_count(), 1);
+    }
+}
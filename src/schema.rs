@@ -0,0 +1,190 @@
+use serde::{Deserialize, Serialize};
+
+use crate::analyzer::{AnalyzerConfig, TextAnalyzer};
+
+/// Name of the only field of the indexes created without a schema.
+pub const DEFAULT_FIELD: &str = "text";
+
+/// Declares a field of the documents of an index: how its values are analyzed, and
+/// whether they are indexed and stored. Fields are indexed but not stored by default.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FieldEntry {
+    name: String,
+    analyzer: TextAnalyzer, // analyzes both the values of the field and the queries on it
+    indexed: bool,
+    stored: bool,
+}
+
+impl FieldEntry {
+    pub fn new(name: &str) -> Self {
+        FieldEntry {
+            name: name.to_string(),
+            analyzer: TextAnalyzer::default(),
+            indexed: true,
+            stored: false,
+        }
+    }
+
+    pub fn with_analyzer(mut self, config: AnalyzerConfig) -> Self {
+        self.analyzer = config.build();
+        self
+    }
+
+    /// Sets whether the values of the field are searchable.
+    pub fn with_indexed(mut self, indexed: bool) -> Self {
+        self.indexed = indexed;
+        self
+    }
+
+    /// Sets whether the values of the field are kept to be returned with search results.
+    pub fn with_stored(mut self, stored: bool) -> Self {
+        self.stored = stored;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn analyzer(&self) -> &TextAnalyzer {
+        &self.analyzer
+    }
+
+    pub fn is_indexed(&self) -> bool {
+        self.indexed
+    }
+
+    pub fn is_stored(&self) -> bool {
+        self.stored
+    }
+}
+
+/// The fields of the documents of an index, and optionally the field holding the
+/// external id of each document.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Schema {
+    fields: Vec<FieldEntry>,
+    unique_key: Option<String>,
+}
+
+impl Schema {
+    pub fn new() -> Self {
+        Schema::default()
+    }
+
+    /// Adds a field, replacing any field with the same name.
+    pub fn with_field(mut self, field: FieldEntry) -> Self {
+        match self.fields.iter_mut().find(|f| f.name == field.name) {
+            Some(existing) => *existing = field,
+            None => self.fields.push(field),
+        }
+        self
+    }
+
+    /// Declares `field` as the unique key of the documents: every document must have
+    /// exactly one value for it, distinct from those of the other documents. The values
+    /// are taken as they are, without analysis, and the field does not have to be
+    /// indexed or stored.
+    pub fn with_unique_key(mut self, field: &str) -> Self {
+        self.unique_key = Some(field.to_string());
+        self
+    }
+
+    pub fn unique_key(&self) -> Option<&str> {
+        self.unique_key.as_deref()
+    }
+
+    pub fn field(&self, name: &str) -> Option<&FieldEntry> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    pub fn fields(&self) -> &[FieldEntry] {
+        &self.fields
+    }
+
+    /// The field searched by queries that do not name one: the first indexed field.
+    pub fn default_field(&self) -> Option<&FieldEntry> {
+        self.fields.iter().find(|field| field.indexed)
+    }
+}
+
+/// A document to index: values of named fields. A field may have several values, e.g.
+/// tags.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Document {
+    values: Vec<(String, String)>,
+}
+
+impl Document {
+    pub fn new() -> Self {
+        Document::default()
+    }
+
+    pub fn with_value(mut self, field: &str, value: &str) -> Self {
+        self.add_value(field, value);
+        self
+    }
+
+    pub fn add_value(&mut self, field: &str, value: &str) {
+        self.values.push((field.to_string(), value.to_string()));
+    }
+
+    /// The values of all fields, in the order they were added.
+    pub fn values(&self) -> &[(String, String)] {
+        &self.values
+    }
+
+    /// The values of a field, in the order they were added.
+    pub fn get<'a>(&'a self, field: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.values
+            .iter()
+            .filter(move |(name, _)| name == field)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The first value of a field.
+    pub fn get_first<'a>(&'a self, field: &'a str) -> Option<&'a str> {
+        self.get(field).next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Document, FieldEntry, Schema};
+
+    #[test]
+    fn test_schema() {
+        let schema = Schema::new()
+            .with_field(FieldEntry::new("id").with_indexed(false).with_stored(true))
+            .with_field(FieldEntry::new("title"))
+            .with_field(FieldEntry::new("body"))
+            .with_field(FieldEntry::new("title").with_stored(true));
+        assert_eq!(
+            schema
+                .fields()
+                .iter()
+                .map(|field| field.name())
+                .collect::<Vec<&str>>(),
+            vec!["id", "title", "body"]
+        );
+        assert!(schema.field("title").unwrap().is_stored());
+        assert!(schema.field("tags").is_none());
+        assert_eq!(schema.default_field().unwrap().name(), "title");
+        assert_eq!(schema.unique_key(), None);
+        assert_eq!(schema.with_unique_key("id").unique_key(), Some("id"));
+    }
+
+    #[test]
+    fn test_document() {
+        let document = Document::new()
+            .with_value("title", "Sheltie")
+            .with_value("tags", "dog")
+            .with_value("tags", "herding");
+        assert_eq!(document.get_first("title"), Some("Sheltie"));
+        assert_eq!(
+            document.get("tags").collect::<Vec<&str>>(),
+            vec!["dog", "herding"]
+        );
+        assert_eq!(document.get_first("body"), None);
+    }
+}
@@ -1,71 +1,332 @@
+use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::cmp::Reverse;
 use std::collections::BinaryHeap;
+use std::sync::Arc;
 
-use crate::analyzer::analyze;
-use crate::index::{Index, PostingsList};
+use crate::analyzer::Analyzer;
+use crate::commit::Commit;
+use crate::index::{Block, Index, PostingsList, BLOCK_SIZE};
+use crate::query::Query;
+use crate::schema::Document;
+use crate::similarity::{Bm25, CollectionStats, Similarity, TermStats};
 
+/// Searches the segments of an index. Documents are numbered across segments: the
+/// doc_ids of a segment follow those of the segments before it.
+///
+/// Terms are weighed with their statistics in all the segments, so that the scores of
+/// documents of different segments can be compared. The statistics include the deleted
+/// documents until they are merged away, so the score of a document may change slightly
+/// as segments are merged.
 pub struct Searcher<'a> {
-    index: &'a Index,
+    segments: Segments<'a>,
+    doc_bases: Vec<usize>, // number of doc_ids in the segments before each segment
+    similarity: Box<dyn Similarity>,
 }
 
 impl<'a> Searcher<'a> {
+    /// Creates a searcher over a single segment, whose doc_ids are left as they are.
     pub fn new(index: &'a Index) -> Self {
-        Self { index: &index }
-    }
-
-    // Search inverted index by document-at-a-time manner using binary heaps
-    pub fn search(&self, text: &String, k: usize) -> Vec<SearchResult> {
-        let results = {
-            let tokens = &analyze(text);
-            let mut terms = {
-                let mut terms = BinaryHeap::with_capacity(tokens.len());
-                // Set the cursors of all postings lists. The cursors points will be
-                // sorted by a binary heap (min-heap).
-                for token in tokens {
-                    if let Some(postings_list) = self.index.get_postings_list(&token.token) {
-                        let cursor = Cursor::new(postings_list);
-                        if let Some(cursor) = cursor {
-                            terms.push(Reverse(cursor));
-                        }
+        Self::with_segments(std::slice::from_ref(index))
+    }
+
+    /// Creates a searcher over all the segments of a commit.
+    pub fn with_segments(segments: &'a [Index]) -> Self {
+        Self::with(Segments::Borrowed(segments))
+    }
+
+    /// Creates a searcher over all the segments of a shared commit, which it keeps alive.
+    pub fn with_commit(commit: Arc<Commit>) -> Searcher<'static> {
+        Searcher::with(Segments::Shared(commit))
+    }
+
+    fn with(segments: Segments<'a>) -> Self {
+        let mut doc_bases = Vec::with_capacity(segments.get().len());
+        let mut doc_base = 0;
+        for segment in segments.get() {
+            doc_bases.push(doc_base);
+            doc_base += segment.max_doc_id();
+        }
+        Self {
+            segments,
+            doc_bases,
+            similarity: Box::new(Bm25::default()),
+        }
+    }
+
+    fn segments(&self) -> &[Index] {
+        self.segments.get()
+    }
+
+    /// Sets the scoring model used by `search` and `search_query`. Defaults to BM25.
+    pub fn with_similarity<S: Similarity + 'static>(mut self, similarity: S) -> Self {
+        self.similarity = Box::new(similarity);
+        self
+    }
+
+    /// The values of the stored fields of a document, e.g. of a search result.
+    pub fn doc(&self, doc_id: usize) -> Option<Document> {
+        let segment = self
+            .doc_bases
+            .partition_point(|&doc_base| doc_base < doc_id)
+            .checked_sub(1)?;
+        self.segments()[segment].doc(doc_id - self.doc_bases[segment])
+    }
+
+    /// The doc_id of the document whose unique key is `id`.
+    pub fn doc_id(&self, id: &str) -> Option<usize> {
+        self.segments()
+            .iter()
+            .zip(&self.doc_bases)
+            .find_map(|(segment, doc_base)| Some(doc_base + segment.doc_id(id)?))
+    }
+
+    /// Executes a query scored with the similarity of this searcher and returns the top
+    /// `k` live documents.
+    pub fn search_query(&self, query: &dyn Query, k: usize) -> Vec<SearchResult> {
+        let mut results = TopDocs::new(k);
+        let stats = CollectionStats::new(self.segments());
+        for (segment, doc_base) in self.segments().iter().zip(&self.doc_bases) {
+            if let Some(mut scorer) = query.scorer(segment, &stats, self.similarity.as_ref()) {
+                while let Some(doc_id) = scorer.doc() {
+                    if segment.is_live(doc_id) {
+                        results.push(ScoredDoc {
+                            doc_id: doc_base + doc_id,
+                            score: scorer.score(),
+                        });
                     }
+                    scorer.next();
                 }
-                terms
-            };
-            let mut results = BinaryHeap::with_capacity(k);
-            while let Some(Reverse(mut cursor_min)) = terms.pop() {
-                // doc_id is a document ID which is now processing.
-                if let Some(doc_id) = cursor_min.next_doc {
-                    // process first document.
-                    let mut score = 1.0f64; // cumulative score of the document, fixed score for now.
-                    if cursor_min.next() {
-                        terms.push(Reverse(cursor_min));
-                    }
-                    while let Some(Reverse(cursor)) = terms.peek() {
-                        if cursor.next_doc != Some(doc_id) {
-                            break;
-                        }
-                        if let Some(Reverse(mut cursor)) = terms.pop() {
-                            if let Some(_) = cursor.next_doc {
-                                score += 1.0f64; // fixed score for now.
-                                if cursor.next() {
-                                    terms.push(Reverse(cursor));
-                                }
-                            }
-                        }
-                    }
-                    results.push(ScoredDoc {
-                        doc_id: doc_id,
-                        score: score,
+            }
+        }
+        results.into_results()
+    }
+
+    /// Searches the default field of the index for the tokens its analyzer extracts from
+    /// `text`, document-at-a-time with Block-Max WAND dynamic pruning.
+    /// The cursors are kept sorted by their current doc_id, and the documents whose score
+    /// upper bound, per term and then per block of postings, cannot beat the worst of the
+    /// current top k are skipped without being scored. The results are the same as those
+    /// of an exhaustive evaluation.
+    pub fn search(&self, text: &str, k: usize) -> Vec<SearchResult> {
+        self.search_in(None, text, k)
+    }
+
+    /// Like `search`, but searches `field` instead of the default field of the index.
+    pub fn search_field(&self, field: &str, text: &str, k: usize) -> Vec<SearchResult> {
+        self.search_in(Some(field), text, k)
+    }
+
+    fn search_in(&self, field: Option<&str>, text: &str, k: usize) -> Vec<SearchResult> {
+        let mut results = TopDocs::new(k);
+        if k > 0 {
+            let stats = CollectionStats::new(self.segments());
+            for (segment, &doc_base) in self.segments().iter().zip(&self.doc_bases) {
+                self.search_segment(segment, doc_base, &stats, field, text, &mut results);
+            }
+        }
+        results.into_results()
+    }
+
+    /// Adds the documents of a segment that make it to the top k to `results`. The
+    /// threshold of the documents of the previous segments carries over.
+    fn search_segment(
+        &self,
+        segment: &Index,
+        doc_base: usize,
+        stats: &CollectionStats<'_>,
+        field: Option<&str>,
+        text: &str,
+        results: &mut TopDocs,
+    ) {
+        let (entry, field) = match segment.resolve_field(field) {
+            Some(field) => field,
+            None => return,
+        };
+
+        let tokens = entry.analyzer().analyze(text);
+        let mut terms = Vec::with_capacity(tokens.len());
+        for (ord, token) in tokens.iter().enumerate() {
+            if let Some(postings_list) = field.get_postings_list(&token.token) {
+                let stats = stats.term_stats(entry.name(), &token.token);
+                let max_score = postings_list.max_score(self.similarity.as_ref(), &stats);
+                if let Some(cursor) = Cursor::new(postings_list) {
+                    terms.push(TermCursor {
+                        ord,
+                        max_score,
+                        stats,
+                        cursor,
                     });
                 }
             }
-            results
-        };
-        results
-            .iter()
-            .take(k)
-            .map(|r| SearchResult {
+        }
+
+        loop {
+            terms.retain(|term| term.cursor.next_doc.is_some());
+            terms.sort_by_key(|term| term.cursor.next_doc);
+
+            // Find the pivot: the first cursor at which the upper bounds of the cursors so
+            // far add up to more than the current threshold. No document before the doc_id
+            // of the pivot can make it to the top k. The sums of upper bounds are inflated
+            // by the worst-case rounding error, as documents are scored by summing in a
+            // different order.
+            let threshold = results.threshold();
+            let beats_threshold = |upper_bound: f64, terms: usize| {
+                let slack = 1.0 + terms as f64 * f64::EPSILON;
+                threshold.is_none_or(|threshold| upper_bound * slack > threshold)
+            };
+            let mut upper_bound = 0.0;
+            let mut pivot = None;
+            for (i, term) in terms.iter().enumerate() {
+                upper_bound += term.max_score;
+                if beats_threshold(upper_bound, i + 1) {
+                    pivot = Some(i);
+                    break;
+                }
+            }
+            let (mut pivot, pivot_doc) = match pivot
+                .and_then(|pivot| terms[pivot].cursor.next_doc.map(|doc_id| (pivot, doc_id)))
+            {
+                Some(pivot) => pivot,
+                None => break,
+            };
+            while terms
+                .get(pivot + 1)
+                .is_some_and(|term| term.cursor.next_doc == Some(pivot_doc))
+            {
+                pivot += 1;
+            }
+
+            // Check the tighter upper bound given by the blocks the pivot document falls in.
+            // If it is not enough either, skip to the end of the first of these blocks.
+            let block_upper_bound = terms[..=pivot]
+                .iter()
+                .map(|term| term.block_max_score(pivot_doc, self.similarity.as_ref()))
+                .sum::<f64>();
+            if !beats_threshold(block_upper_bound, pivot + 1) {
+                let block_end = terms[..=pivot]
+                    .iter()
+                    .filter_map(|term| term.cursor.block(pivot_doc))
+                    .map(|block| block.last_doc + 1)
+                    .min()
+                    .unwrap_or(usize::MAX);
+                let target = terms
+                    .get(pivot + 1)
+                    .and_then(|term| term.cursor.next_doc)
+                    .map_or(block_end, |doc_id| doc_id.min(block_end))
+                    .max(pivot_doc + 1);
+                for term in terms[..=pivot].iter_mut() {
+                    term.cursor.advance(target);
+                }
+                continue;
+            }
+
+            if terms[0].cursor.next_doc == Some(pivot_doc) && !segment.is_live(pivot_doc) {
+                // Skip the deleted pivot document.
+                for term in terms[..=pivot].iter_mut() {
+                    term.cursor.next();
+                }
+            } else if terms[0].cursor.next_doc == Some(pivot_doc) {
+                // Score the pivot document, adding up the term scores in query order.
+                let mut matched = terms[..=pivot].iter_mut().collect::<Vec<&mut TermCursor>>();
+                matched.sort_by_key(|term| term.ord);
+                let doc_length = field.doc_length(pivot_doc);
+                let mut score = 0.0;
+                for term in matched {
+                    score += self
+                        .similarity
+                        .score(&term.stats, term.cursor.freq(), doc_length);
+                    term.cursor.next();
+                }
+                results.push(ScoredDoc {
+                    doc_id: doc_base + pivot_doc,
+                    score,
+                });
+            } else {
+                // Skip the documents before the pivot.
+                for term in terms[..pivot].iter_mut() {
+                    term.cursor.advance(pivot_doc);
+                }
+            }
+        }
+    }
+}
+
+/// The segments searched by a `Searcher`, borrowed or shared with other searchers.
+enum Segments<'a> {
+    Borrowed(&'a [Index]),
+    Shared(Arc<Commit>),
+}
+
+impl Segments<'_> {
+    fn get(&self) -> &[Index] {
+        match self {
+            Segments::Borrowed(segments) => segments,
+            Segments::Shared(commit) => commit.segments(),
+        }
+    }
+}
+
+/// A cursor over the postings list of a query term, with the statistics needed to score
+/// it and bound its score.
+struct TermCursor<'a> {
+    ord: usize, // position of the term in the query
+    max_score: f64,
+    stats: TermStats,
+    cursor: Cursor<'a>,
+}
+
+impl TermCursor<'_> {
+    /// Upper bound of the score of the term in the block of postings that would contain
+    /// `target`. Zero if there is no such block.
+    fn block_max_score(&self, target: usize, similarity: &dyn Similarity) -> f64 {
+        self.cursor.block(target).map_or(0.0, |block| {
+            block.impacts.max_score(similarity, &self.stats)
+        })
+    }
+}
+
+/// Collects the `k` best scored documents in a bounded min-heap, so that the worst of
+/// the current top `k` can be replaced in logarithmic time.
+pub struct TopDocs {
+    k: usize,
+    heap: BinaryHeap<Reverse<ScoredDoc>>,
+}
+
+impl TopDocs {
+    pub fn new(k: usize) -> Self {
+        TopDocs {
+            k,
+            heap: BinaryHeap::with_capacity(k),
+        }
+    }
+
+    pub fn push(&mut self, scored_doc: ScoredDoc) {
+        if self.heap.len() < self.k {
+            self.heap.push(Reverse(scored_doc));
+        } else if let Some(mut worst) = self.heap.peek_mut() {
+            if scored_doc > worst.0 {
+                *worst = Reverse(scored_doc);
+            }
+        }
+    }
+
+    /// The score a document has to beat to make it to the top `k`, if there are already
+    /// `k` results.
+    pub fn threshold(&self) -> Option<f64> {
+        if self.heap.len() < self.k {
+            None
+        } else {
+            self.heap.peek().map(|worst| worst.0.rank())
+        }
+    }
+
+    /// Returns the results ordered by descending score, then by ascending doc_id.
+    pub fn into_results(self) -> Vec<SearchResult> {
+        self.heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(r)| SearchResult {
                 doc_id: r.doc_id,
                 score: r.score,
             })
@@ -80,59 +341,163 @@ pub struct SearchResult {
     pub score: f64,
 }
 
+/// A document ordered by how good a hit it is: a higher score is better, and of two
+/// documents with the same score the one with the smaller doc_id is better. A NaN score,
+/// whatever its sign, ranks as `f64::NEG_INFINITY`, below every other score.
+#[derive(Debug)]
 pub struct ScoredDoc {
     pub doc_id: usize,
     pub score: f64,
 }
 
+impl ScoredDoc {
+    /// The score the document is ranked by.
+    fn rank(&self) -> f64 {
+        if self.score.is_nan() {
+            f64::NEG_INFINITY
+        } else {
+            self.score
+        }
+    }
+}
+
 impl Ord for ScoredDoc {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.score
-            .partial_cmp(&other.score)
-            .unwrap_or(Ordering::Less) // should fail?
+        self.rank()
+            .total_cmp(&other.rank())
+            .then_with(|| other.doc_id.cmp(&self.doc_id))
     }
 }
 
 impl PartialOrd for ScoredDoc {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        self.score.partial_cmp(&other.score)
+        Some(self.cmp(other))
     }
 }
 
 impl PartialEq for ScoredDoc {
     fn eq(&self, other: &Self) -> bool {
-        self.score == other.score
+        self.cmp(other) == Ordering::Equal
     }
 }
 
 impl Eq for ScoredDoc {}
 
+/// Cursor over a postings list. The postings are decoded lazily, one block at a time, so
+/// the blocks that are skipped over are never decoded, nor read from disk if the postings
+/// list is memory-mapped. A block that cannot be decoded ends the cursor, as if the
+/// postings list stopped before it: `Commit::check_integrity` reports such corruption.
 #[derive(Debug)]
 pub struct Cursor<'a> {
-    pub postings_list: &'a PostingsList,
+    pub postings_list: Cow<'a, PostingsList>,
     pub position: usize, // index position for the postings list
     pub next_doc: Option<usize>,
+    block: usize,     // index of the decoded block
+    docs: Vec<usize>, // decoded doc_ids of the block
+    freqs: Vec<u32>,  // decoded freqs of the block
 }
 
 impl<'a> Cursor<'a> {
-    pub fn new(postings_list: &'a PostingsList) -> Option<Self> {
-        let head = 0;
-        postings_list.get_doc_id(head).map(|doc_id| Cursor {
+    pub fn new(postings_list: Cow<'a, PostingsList>) -> Option<Self> {
+        let mut cursor = Cursor {
             postings_list,
             position: 0,
-            next_doc: Some(doc_id),
-        })
+            next_doc: None,
+            block: 0,
+            docs: Vec::with_capacity(BLOCK_SIZE),
+            freqs: Vec::with_capacity(BLOCK_SIZE),
+        };
+        cursor
+            .postings_list
+            .decode_block(0, &mut cursor.docs, &mut cursor.freqs)?;
+        cursor.next_doc = Some(*cursor.docs.first()?);
+        Some(cursor)
     }
 
+    /// Decodes a block, unless it is the current one. Returns `false` if it cannot be
+    /// decoded.
+    fn load_block(&mut self, block: usize) -> bool {
+        if block != self.block {
+            self.block = block;
+            if self
+                .postings_list
+                .decode_block(block, &mut self.docs, &mut self.freqs)
+                .is_none()
+            {
+                self.docs.clear();
+                return false;
+            }
+        }
+        true
+    }
+
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> bool {
-        let next_doc = self.postings_list.get_doc_id(self.position + 1);
-        if let Some(next_doc) = next_doc {
-            self.position += 1;
-            self.next_doc = Some(next_doc);
-            true
+        if self.next_doc.is_none() || self.position + 1 >= self.postings_list.len() {
+            self.next_doc = None;
+            return false;
+        }
+        self.position += 1;
+        self.next_doc = if self.load_block(self.position / BLOCK_SIZE) {
+            self.docs.get(self.position % BLOCK_SIZE).copied()
         } else {
+            None
+        };
+        self.next_doc.is_some()
+    }
+
+    /// Moves to the first document whose doc_id is `>= target`, skipping whole blocks of
+    /// postings without decoding them. Does nothing if the current document already
+    /// satisfies it. Returns `false` once the postings list is exhausted.
+    pub fn advance(&mut self, target: usize) -> bool {
+        match self.next_doc {
+            Some(doc_id) if doc_id >= target => return true,
+            Some(_) => {}
+            None => return false,
+        }
+        let block = self.postings_list.find_block(self.position, target);
+        if self.postings_list.get_block(block).is_none() {
             self.next_doc = None;
-            false
+            return false;
+        }
+        let start = if block == self.block {
+            self.position % BLOCK_SIZE
+        } else {
+            0
+        };
+        if !self.load_block(block) {
+            self.next_doc = None;
+            return false;
+        }
+        let index = start + self.docs[start..].partition_point(|&doc_id| doc_id < target);
+        self.position = block * BLOCK_SIZE + index;
+        self.next_doc = self.docs.get(index).copied();
+        self.next_doc.is_some()
+    }
+
+    /// Returns the skip data of the block that would contain `target`, without moving
+    /// the cursor.
+    pub fn block(&self, target: usize) -> Option<&Block> {
+        let block = self.postings_list.find_block(self.position, target);
+        self.postings_list.get_block(block)
+    }
+
+    pub fn freq(&self) -> u32 {
+        match self.next_doc {
+            Some(_) => self.freqs[self.position % BLOCK_SIZE],
+            None => 0,
+        }
+    }
+
+    /// Decodes the positions of the term in the current document. Returns no positions if
+    /// they cannot be decoded.
+    pub fn positions(&self) -> Vec<u32> {
+        match self.next_doc {
+            Some(_) => self
+                .postings_list
+                .decode_positions(self.block, &self.freqs, self.position % BLOCK_SIZE)
+                .unwrap_or_default(),
+            None => vec![],
         }
     }
 }
@@ -145,7 +510,7 @@ impl Ord for Cursor<'_> {
 
 impl PartialOrd for Cursor<'_> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.next_doc.cmp(&other.next_doc))
+        Some(self.cmp(other))
     }
 }
 
@@ -159,27 +524,415 @@ impl Eq for Cursor<'_> {}
 
 #[cfg(test)]
 mod tests {
-    use super::Searcher;
-    use crate::index::Index;
+    use std::borrow::Cow;
+    use std::cmp::Ordering;
+    use std::sync::Arc;
+
+    use super::{Cursor, ScoredDoc, Searcher, TopDocs};
+    use crate::analyzer::dictionary::DictionaryConfig;
+    use crate::analyzer::{AnalyzerConfig, TokenFilterConfig, TokenizerConfig};
+    use crate::index::{Index, PostingsList};
+    use crate::query::boolean::BooleanQuery;
+    use crate::query::phrase::PhraseQuery;
+    use crate::query::term::TermQuery;
+    use crate::query::{Occur, Query};
+    use crate::similarity::{
+        Bm25, Dfr, LmDirichlet, LmJelinekMercer, Similarity, TermStats, TfIdf,
+    };
 
     #[test]
     fn test_search() {
         let index = {
             let mut index = Index::new();
-            index.add(&String::from("two one two"));
-            index.add(&String::from("one two three two three three"));
+            index.add(&String::from("two one two")).unwrap();
+            index
+                .add(&String::from("one two three two three three"))
+                .unwrap();
             index
         };
 
-        let searcher = Searcher { index: &index };
+        let searcher = Searcher::new(&index);
 
-        let results = searcher.search(&"one".to_string(), 10);
+        let results = searcher.search("one", 10);
         assert_eq!(results.len(), 2);
-        let results = searcher.search(&"two".to_string(), 10);
+        let results = searcher.search("two", 10);
         assert_eq!(results.len(), 2);
-        let results = searcher.search(&"one two".to_string(), 10);
+        let results = searcher.search("one two", 10);
         assert_eq!(results.len(), 2);
-        let results = searcher.search(&"three".to_string(), 10);
+        let results = searcher.search("three", 10);
+        assert_eq!(results.len(), 1);
+    }
+
+    #[test]
+    fn test_search_analyzed() {
+        let index = {
+            let mut index = Index::with_analyzer(AnalyzerConfig::new(
+                TokenizerConfig::Whitespace,
+                vec![
+                    TokenFilterConfig::StripPunctuation,
+                    TokenFilterConfig::Lowercase,
+                ],
+            ));
+            index.add("Hello, World!").unwrap();
+            index.add("hello there").unwrap();
+            index
+        };
+        let searcher = Searcher::new(&index);
+
+        assert_eq!(searcher.search("HELLO!", 10).len(), 2);
+        assert_eq!(searcher.search("world", 10).len(), 1);
+        assert_eq!(searcher.search("...", 10).len(), 0);
+    }
+
+    #[test]
+    fn test_search_japanese() {
+        let tokenizers = vec![
+            TokenizerConfig::CjkBigram,
+            TokenizerConfig::Dictionary(DictionaryConfig::japanese()),
+        ];
+        for tokenizer in tokenizers {
+            let index = {
+                let mut index = Index::with_analyzer(AnalyzerConfig::new(tokenizer, vec![]));
+                index.add("東京タワーに行きました").unwrap();
+                index.add("京都の大学で日本語を話します").unwrap();
+                index.add("東京の天気").unwrap();
+                index
+            };
+            let searcher = Searcher::new(&index);
+            let doc_ids = |text: &str| {
+                let mut doc_ids = searcher
+                    .search(text, 10)
+                    .iter()
+                    .map(|r| r.doc_id)
+                    .collect::<Vec<usize>>();
+                doc_ids.sort_unstable();
+                doc_ids
+            };
+
+            assert_eq!(doc_ids("東京"), vec![1, 3]);
+            assert_eq!(doc_ids("日本語"), vec![2]);
+            assert_eq!(doc_ids("東京タワー"), vec![1, 3]);
+
+            // A multi-token term is matched as a phrase.
+            let query = TermQuery::new("東京タワー".to_string());
+            assert_eq!(
+                query
+                    .execute(&index)
+                    .iter()
+                    .map(|r| r.doc_id)
+                    .collect::<Vec<usize>>(),
+                vec![1]
+            );
+        }
+    }
+
+    #[test]
+    fn test_search_top_k() {
+        let index = {
+            let mut index = Index::new();
+            index.add("a").unwrap();
+            index.add("a a b").unwrap();
+            index.add("b c").unwrap();
+            index.add("a c c c").unwrap();
+            index.add("a c").unwrap();
+            index.add("c").unwrap();
+            index.add("a").unwrap();
+            index
+        };
+        let searcher = Searcher::new(&index);
+
+        // The top k are the best k of the full result list, in the same order.
+        let all = searcher.search("a c", 10);
+        assert_eq!(all.len(), 7);
+        for pair in all.windows(2) {
+            assert!(
+                pair[0].score > pair[1].score
+                    || (pair[0].score == pair[1].score && pair[0].doc_id < pair[1].doc_id)
+            );
+        }
+        for k in 0..=7 {
+            let top = searcher.search("a c", k);
+            assert_eq!(
+                top.iter().map(|r| r.doc_id).collect::<Vec<usize>>(),
+                all.iter().take(k).map(|r| r.doc_id).collect::<Vec<usize>>()
+            );
+        }
+
+        // Ties are broken by doc_id.
+        let top = searcher.search("a", 2);
+        let top_doc_ids = top.iter().map(|r| r.doc_id).collect::<Vec<usize>>();
+        assert_eq!(top_doc_ids, vec![1, 7]);
+        assert_eq!(top[0].score, top[1].score);
+
+        let query = TermQuery::new("a".to_string());
+        let top = searcher.search_query(&query, 2);
+        assert_eq!(
+            top.iter().map(|r| r.doc_id).collect::<Vec<usize>>(),
+            top_doc_ids
+        );
+    }
+
+    #[test]
+    fn test_scored_doc_ordering() {
+        let doc = |doc_id, score| ScoredDoc { doc_id, score };
+
+        assert!(doc(1, 2.0) > doc(2, 1.0));
+        assert!(doc(1, 1.0) > doc(2, 1.0));
+        assert!(doc(2, 1.0) < doc(1, 1.0));
+        assert_eq!(doc(1, 1.0), doc(1, 1.0));
+        // NaN ranks lowest, whatever its sign.
+        assert!(doc(1, f64::NAN) < doc(2, -1.0));
+        assert!(doc(1, -f64::NAN) < doc(2, -1.0));
+        assert!(doc(1, f64::NAN) > doc(2, f64::NEG_INFINITY));
+        assert_eq!(doc(1, f64::NAN).cmp(&doc(1, -f64::NAN)), Ordering::Equal);
+
+        let mut top = TopDocs::new(3);
+        for (doc_id, score) in [(1, 0.5), (2, f64::NAN), (3, 2.0), (4, 2.0), (5, 1.0)] {
+            top.push(doc(doc_id, score));
+        }
+        let results = top.into_results();
+        assert_eq!(
+            results.iter().map(|r| r.doc_id).collect::<Vec<usize>>(),
+            vec![3, 4, 5]
+        );
+        let mut top = TopDocs::new(2);
+        top.push(doc(1, f64::NAN));
+        top.push(doc(2, -f64::NAN));
+        assert_eq!(top.threshold(), Some(f64::NEG_INFINITY));
+    }
+
+    #[test]
+    fn test_search_wand() {
+        // Pseudo-random corpus with a skewed term distribution.
+        let mut seed = 42u64;
+        let mut random = move |n: u64| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) % n
+        };
+        let words = (0..30).map(|i| format!("w{}", i)).collect::<Vec<String>>();
+        let index = {
+            let mut index = Index::new();
+            for _ in 0..1000 {
+                let length = 1 + random(20);
+                let text = (0..length)
+                    .map(|_| words[(random(30) * random(30) / 29) as usize].as_str())
+                    .collect::<Vec<&str>>()
+                    .join(" ");
+                index.add(&text).unwrap();
+            }
+            index
+        };
+
+        let similarities: Vec<Box<dyn Fn() -> Box<dyn Similarity>>> = vec![
+            Box::new(|| Box::new(Bm25::default())),
+            Box::new(|| Box::new(TfIdf)),
+            Box::new(|| Box::new(LmDirichlet::new(100.0))),
+            Box::new(|| Box::new(Dfr::default())),
+        ];
+        for similarity in similarities {
+            let searcher = Searcher {
+                similarity: similarity(),
+                ..Searcher::new(&index)
+            };
+            for _ in 0..50 {
+                let terms = (0..1 + random(5))
+                    .map(|_| words[random(30) as usize].clone())
+                    .collect::<Vec<String>>();
+                let exhaustive = BooleanQuery::new(
+                    terms
+                        .iter()
+                        .map(|term| {
+                            let query: Box<dyn Query> = Box::new(TermQuery::new(term.clone()));
+                            (Occur::Should, query)
+                        })
+                        .collect(),
+                )
+                .execute_with(&index, similarity().as_ref());
+                for &k in &[1, 3, 10, 100] {
+                    let results = searcher.search(&terms.join(" "), k);
+                    assert_eq!(
+                        format!("{:?}", results),
+                        format!("{:?}", exhaustive.iter().take(k).collect::<Vec<_>>())
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_cursor_advance() {
+        let mut postings_list = PostingsList::new();
+        for doc_id in (1..=1000).map(|i| i * 3) {
+            postings_list.add(doc_id, vec![0; doc_id % 5 + 1], 10);
+        }
+
+        let mut cursor = Cursor::new(Cow::Borrowed(&postings_list)).unwrap();
+        assert!(cursor.advance(1));
+        assert_eq!(cursor.next_doc, Some(3));
+        assert!(cursor.advance(4));
+        assert_eq!(cursor.next_doc, Some(6));
+        assert!(cursor.advance(6));
+        assert_eq!(cursor.next_doc, Some(6));
+        assert!(cursor.advance(500));
+        assert_eq!(cursor.next_doc, Some(501));
+        assert_eq!(cursor.freq(), 2);
+        // Never moves backwards.
+        assert!(cursor.advance(100));
+        assert_eq!(cursor.next_doc, Some(501));
+        assert!(cursor.next());
+        assert_eq!(cursor.next_doc, Some(504));
+        assert_eq!(cursor.block(504).unwrap().last_doc, 768);
+        assert_eq!(cursor.block(769).unwrap().last_doc, 1152);
+        assert!(cursor.block(3001).is_none());
+        assert!(cursor.advance(3000));
+        assert_eq!(cursor.next_doc, Some(3000));
+        assert!(!cursor.advance(3001));
+        assert_eq!(cursor.next_doc, None);
+        assert!(!cursor.advance(3002));
+
+        // Agrees with stepping through every posting.
+        for target in 0..3005 {
+            let mut cursor = Cursor::new(Cow::Borrowed(&postings_list)).unwrap();
+            let mut expected = Cursor::new(Cow::Borrowed(&postings_list)).unwrap();
+            while expected.next_doc.is_some_and(|doc_id| doc_id < target) {
+                expected.next();
+            }
+            assert_eq!(cursor.advance(target), expected.next_doc.is_some());
+            assert_eq!(cursor.next_doc, expected.next_doc);
+            if cursor.next_doc.is_some() {
+                assert_eq!(cursor.position, expected.position);
+            }
+        }
+    }
+
+    #[test]
+    fn test_search_bm25() {
+        let index = {
+            let mut index = Index::new();
+            index.add("a b c d").unwrap();
+            index.add("a a b c").unwrap();
+            index.add("a b").unwrap();
+            index.add("c d").unwrap();
+            index
+        };
+
+        let searcher = Searcher::new(&index);
+        let bm25 = Bm25::default();
+        let score = |doc_id: usize, query: &str| {
+            searcher
+                .search(query, 10)
+                .iter()
+                .find(|r| r.doc_id == doc_id)
+                .map(|r| r.score)
+        };
+        let stats = |doc_freq, collection_freq| TermStats {
+            doc_freq,
+            collection_freq,
+            doc_count: 4,
+            total_length: 12,
+            avg_doc_length: 3.0,
+        };
+
+        // A single term is scored by BM25 with the stored frequency and lengths.
+        assert_eq!(score(2, "a"), Some(bm25.score(&stats(3, 4), 2, 4)));
+        // Higher term frequency ranks higher, as does a shorter document.
+        assert!(score(2, "a") > score(1, "a"));
+        assert!(score(3, "a") > score(1, "a"));
+        // Scores of the query terms add up.
+        let expected = bm25.score(&stats(3, 4), 1, 2) + bm25.score(&stats(3, 3), 1, 2);
+        assert_eq!(score(3, "a b"), Some(expected));
+        // Rare terms contribute more than common ones.
+        assert!(score(4, "a d") > score(3, "a d"));
+        assert_eq!(score(4, "z"), None);
+
+        // Parameters are configurable.
+        let searcher = Searcher::new(&index).with_similarity(Bm25::new(1.2, 0.0));
+        let results = searcher.search("d", 10);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].score, results[1].score);
+    }
+
+    #[test]
+    fn test_search_segments() {
+        let texts = ["a b c d", "a a b c", "a b", "c d", "b d", "a"];
+        let index = |texts: &[&str]| {
+            let mut index = Index::new();
+            for text in texts {
+                index.add(text).unwrap();
+            }
+            index
+        };
+        let single = [index(&texts)];
+        let segments = [index(&texts[..4]), index(&texts[4..])];
+
+        // Documents of different segments are scored with the statistics of all of them,
+        // as if they were in a single segment.
+        let single = Searcher::with_segments(&single);
+        let segments = Searcher::with_segments(&segments);
+        for text in ["a", "d", "a b", "a c d"] {
+            assert_eq!(
+                format!("{:?}", segments.search(text, 10)),
+                format!("{:?}", single.search(text, 10))
+            );
+            let query = TermQuery::new(text.to_string());
+            assert_eq!(
+                format!("{:?}", segments.search_query(&query, 10)),
+                format!("{:?}", single.search_query(&query, 10))
+            );
+        }
+        let query = PhraseQuery::new(vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(
+            format!("{:?}", segments.search_query(&query, 10)),
+            format!("{:?}", single.search_query(&query, 10))
+        );
+    }
+
+    #[test]
+    fn test_similarity() {
+        let index = {
+            let mut index = Index::new();
+            index.add("a b c d").unwrap();
+            index.add("a a b c").unwrap();
+            index.add("c d").unwrap();
+            index
+        };
+        let stats = TermStats {
+            doc_freq: 2,
+            collection_freq: 3,
+            doc_count: 3,
+            total_length: 10,
+            avg_doc_length: 10.0 / 3.0,
+        };
+
+        for similarity in [
+            Box::new(TfIdf) as Box<dyn Similarity>,
+            Box::new(LmDirichlet::default()),
+            Box::new(LmJelinekMercer::default()),
+            Box::new(Dfr::default()),
+        ] {
+            let expected = similarity.score(&stats, 2, 4);
+            let searcher = Searcher {
+                similarity,
+                ..Searcher::new(&index)
+            };
+            let results = searcher.search("a", 10);
+            let result = results.iter().find(|r| r.doc_id == 2).unwrap();
+            assert_eq!(result.score, expected);
+
+            // Queries executed through the searcher use its similarity.
+            let query = TermQuery::new("a".to_string());
+            let results = searcher.search_query(&query, 10);
+            let result = results.iter().find(|r| r.doc_id == 2).unwrap();
+            assert_eq!(result.score, expected);
+        }
+
+        // A similarity set on the query takes precedence.
+        let searcher = Searcher::new(&index).with_similarity(TfIdf);
+        let query = TermQuery::new("a".to_string()).with_similarity(Arc::new(Dfr::default()));
+        let results = searcher.search_query(&query, 1);
         assert_eq!(results.len(), 1);
+        assert_eq!(results[0].score, Dfr::default().score(&stats, 2, 4));
     }
 }
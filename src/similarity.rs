@@ -0,0 +1,379 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use crate::index::{FieldIndex, Index, PostingsList};
+
+/// Index-wide statistics of a term, used by similarities to weigh its occurrences.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TermStats {
+    pub doc_freq: usize,      // number of documents containing the term
+    pub collection_freq: u64, // number of occurrences of the term in all documents
+    pub doc_count: usize,     // number of documents in the index
+    pub total_length: u64,    // number of tokens in all documents
+    pub avg_doc_length: f64,  // average number of tokens per document
+}
+
+impl TermStats {
+    pub fn new(field: &FieldIndex, postings_list: &PostingsList) -> Self {
+        TermStats {
+            doc_freq: postings_list.len(),
+            collection_freq: postings_list.collection_freq(),
+            doc_count: field.doc_count(),
+            total_length: field.total_length(),
+            avg_doc_length: field.avg_doc_length(),
+        }
+    }
+
+    /// Probability of a token of the collection being the term, smoothed so that it is
+    /// never zero.
+    fn collection_probability(&self) -> f64 {
+        (self.collection_freq as f64 + 1.0) / (self.total_length as f64 + 1.0)
+    }
+}
+
+/// The statistics of the segments searched together. The `TermStats` of a term add up
+/// its statistics in all the segments, so that the documents of every segment are scored
+/// alike, and their scores can be compared.
+#[derive(Debug)]
+pub struct CollectionStats<'a> {
+    segments: &'a [Index],
+    term_stats: RefCell<HashMap<(String, String), TermStats>>, // by field and term
+}
+
+impl<'a> CollectionStats<'a> {
+    pub fn new(segments: &'a [Index]) -> Self {
+        CollectionStats {
+            segments,
+            term_stats: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// The statistics of `term` in `field`, computed once per term and reused by the
+    /// scorers of all the segments.
+    pub fn term_stats(&self, field: &str, term: &str) -> TermStats {
+        let key = (field.to_string(), term.to_string());
+        if let Some(stats) = self.term_stats.borrow().get(&key) {
+            return *stats;
+        }
+        let (mut doc_freq, mut collection_freq, mut doc_count, mut total_length) = (0, 0, 0, 0);
+        for field in self
+            .segments
+            .iter()
+            .filter_map(|segment| segment.field(field))
+        {
+            if let Some(postings_list) = field.get_postings_list(term) {
+                doc_freq += postings_list.len();
+                collection_freq += postings_list.collection_freq();
+            }
+            doc_count += field.doc_count();
+            total_length += field.total_length();
+        }
+        let stats = TermStats {
+            doc_freq,
+            collection_freq,
+            doc_count,
+            total_length,
+            avg_doc_length: if doc_count > 0 {
+                total_length as f64 / doc_count as f64
+            } else {
+                0.0
+            },
+        };
+        self.term_stats.borrow_mut().insert(key, stats);
+        stats
+    }
+}
+
+/// A scoring model. The score of a document for a query is the sum of the scores of the
+/// query terms it contains.
+///
+/// Scores must be non-negative, must not decrease as `freq` grows and must not increase
+/// as `doc_length` grows. Dynamic pruning relies on this to bound the score of a term.
+pub trait Similarity: std::fmt::Debug + Send + Sync {
+    /// Score contribution of a term occurring `freq` times in a document of `doc_length`
+    /// tokens.
+    fn score(&self, stats: &TermStats, freq: u32, doc_length: u32) -> f64;
+}
+
+/// Okapi BM25 scoring function.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bm25 {
+    k1: f64, // term frequency saturation
+    b: f64,  // strength of document length normalization
+}
+
+impl Bm25 {
+    /// Creates a BM25 similarity. `k1` is raised to at least 0 and `b` is clamped to
+    /// [0, 1], so that scores follow the contract of `Similarity`.
+    pub fn new(k1: f64, b: f64) -> Self {
+        Bm25 {
+            k1: k1.max(0.0),
+            b: if b.is_nan() { 0.0 } else { b.clamp(0.0, 1.0) },
+        }
+    }
+
+    pub fn k1(&self) -> f64 {
+        self.k1
+    }
+
+    pub fn b(&self) -> f64 {
+        self.b
+    }
+
+    /// Inverse document frequency of a term that appears in `doc_freq` of `doc_count`
+    /// documents. Always positive, even for terms appearing in most documents.
+    pub fn idf(&self, doc_freq: usize, doc_count: usize) -> f64 {
+        let doc_freq = doc_freq as f64;
+        let doc_count = doc_count as f64;
+        (1.0 + (doc_count - doc_freq + 0.5) / (doc_freq + 0.5)).ln()
+    }
+}
+
+impl Default for Bm25 {
+    fn default() -> Self {
+        Bm25::new(1.2, 0.75)
+    }
+}
+
+impl Similarity for Bm25 {
+    fn score(&self, stats: &TermStats, freq: u32, doc_length: u32) -> f64 {
+        let idf = self.idf(stats.doc_freq, stats.doc_count);
+        let freq = freq as f64;
+        let norm = if stats.avg_doc_length > 0.0 {
+            1.0 - self.b + self.b * doc_length as f64 / stats.avg_doc_length
+        } else {
+            1.0
+        };
+        idf * freq * (self.k1 + 1.0) / (freq + self.k1 * norm)
+    }
+}
+
+/// Vector space model with square-rooted term frequencies, a smoothed inverse document
+/// frequency and a `1 / sqrt(doc_length)` length norm.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TfIdf;
+
+impl Similarity for TfIdf {
+    fn score(&self, stats: &TermStats, freq: u32, doc_length: u32) -> f64 {
+        let tf = (freq as f64).sqrt();
+        let idf = 1.0 + ((stats.doc_count as f64 + 1.0) / (stats.doc_freq as f64 + 1.0)).ln();
+        let norm = 1.0 / (doc_length.max(1) as f64).sqrt();
+        tf * idf * norm
+    }
+}
+
+/// Query likelihood language model with Dirichlet prior smoothing. Scores are clamped at
+/// zero so that matching a term never lowers the score of a document.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LmDirichlet {
+    mu: f64, // weight of the collection model
+}
+
+impl LmDirichlet {
+    /// Creates a Dirichlet similarity. `mu` is raised to at least `f64::EPSILON`, as
+    /// scores are undefined without smoothing.
+    pub fn new(mu: f64) -> Self {
+        LmDirichlet {
+            mu: mu.max(f64::EPSILON),
+        }
+    }
+
+    pub fn mu(&self) -> f64 {
+        self.mu
+    }
+}
+
+impl Default for LmDirichlet {
+    fn default() -> Self {
+        LmDirichlet::new(2000.0)
+    }
+}
+
+impl Similarity for LmDirichlet {
+    fn score(&self, stats: &TermStats, freq: u32, doc_length: u32) -> f64 {
+        let score = (1.0 + freq as f64 / (self.mu * stats.collection_probability())).ln()
+            + (self.mu / (doc_length as f64 + self.mu)).ln();
+        score.max(0.0)
+    }
+}
+
+/// Query likelihood language model with Jelinek-Mercer smoothing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LmJelinekMercer {
+    lambda: f64, // weight of the collection model, in (0, 1]
+}
+
+impl LmJelinekMercer {
+    /// Creates a Jelinek-Mercer similarity. `lambda` is clamped to [`f64::EPSILON`, 1], as
+    /// scores are undefined without smoothing and negative with a weight above 1.
+    pub fn new(lambda: f64) -> Self {
+        LmJelinekMercer {
+            lambda: if lambda.is_nan() {
+                f64::EPSILON
+            } else {
+                lambda.clamp(f64::EPSILON, 1.0)
+            },
+        }
+    }
+
+    pub fn lambda(&self) -> f64 {
+        self.lambda
+    }
+}
+
+impl Default for LmJelinekMercer {
+    fn default() -> Self {
+        LmJelinekMercer::new(0.7)
+    }
+}
+
+impl Similarity for LmJelinekMercer {
+    fn score(&self, stats: &TermStats, freq: u32, doc_length: u32) -> f64 {
+        let document_probability = freq as f64 / doc_length.max(1) as f64;
+        (1.0 + (1.0 - self.lambda) * document_probability
+            / (self.lambda * stats.collection_probability()))
+        .ln()
+    }
+}
+
+/// Divergence from randomness model InL2: the inverse document frequency basic model,
+/// the Laplace after-effect and the H2 term frequency normalization.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Dfr {
+    c: f64, // strength of the term frequency normalization
+}
+
+impl Dfr {
+    /// Creates a DFR similarity. `c` is raised to at least 0, so that longer documents
+    /// never score higher.
+    pub fn new(c: f64) -> Self {
+        Dfr { c: c.max(0.0) }
+    }
+
+    pub fn c(&self) -> f64 {
+        self.c
+    }
+}
+
+impl Default for Dfr {
+    fn default() -> Self {
+        Dfr::new(1.0)
+    }
+}
+
+impl Similarity for Dfr {
+    fn score(&self, stats: &TermStats, freq: u32, doc_length: u32) -> f64 {
+        let avg_doc_length = if stats.avg_doc_length > 0.0 {
+            stats.avg_doc_length
+        } else {
+            1.0
+        };
+        let tfn = freq as f64 * (1.0 + self.c * avg_doc_length / doc_length.max(1) as f64).log2();
+        let information =
+            tfn * ((stats.doc_count as f64 + 1.0) / (stats.doc_freq as f64 + 0.5)).log2();
+        information / (tfn + 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Bm25, Dfr, LmDirichlet, LmJelinekMercer, Similarity, TermStats, TfIdf};
+
+    fn stats(doc_freq: usize, collection_freq: u64) -> TermStats {
+        TermStats {
+            doc_freq,
+            collection_freq,
+            doc_count: 100,
+            total_length: 1000,
+            avg_doc_length: 10.0,
+        }
+    }
+
+    #[test]
+    fn test_bm25() {
+        let bm25 = Bm25::default();
+
+        // Rare terms weigh more than common ones.
+        assert!(bm25.idf(1, 100) > bm25.idf(10, 100));
+        assert!(bm25.idf(100, 100) > 0.0);
+
+        // Higher term frequencies score higher, but saturate.
+        let idf = bm25.idf(1, 100);
+        let tf1 = bm25.score(&stats(1, 1), 1, 10);
+        let tf2 = bm25.score(&stats(1, 2), 2, 10);
+        let tf100 = bm25.score(&stats(1, 100), 100, 10);
+        assert!(tf1 < tf2 && tf2 < tf100);
+        assert!(tf100 < idf * (bm25.k1() + 1.0));
+
+        // Without length normalization the document length does not matter.
+        let bm25 = Bm25::new(1.2, 0.0);
+        assert_eq!(
+            bm25.score(&stats(1, 1), 1, 5),
+            bm25.score(&stats(1, 1), 1, 20)
+        );
+    }
+
+    #[test]
+    fn test_similarities() {
+        let similarities: Vec<Box<dyn Similarity>> = vec![
+            Box::new(Bm25::default()),
+            Box::new(TfIdf),
+            Box::new(LmDirichlet::new(100.0)),
+            Box::new(LmJelinekMercer::default()),
+            Box::new(Dfr::default()),
+        ];
+        for similarity in similarities {
+            // Higher term frequencies score higher.
+            assert!(
+                similarity.score(&stats(5, 20), 2, 10) > similarity.score(&stats(5, 20), 1, 10),
+                "{:?}",
+                similarity
+            );
+            // Longer documents score lower.
+            assert!(
+                similarity.score(&stats(5, 20), 1, 5) > similarity.score(&stats(5, 20), 1, 20),
+                "{:?}",
+                similarity
+            );
+            // Rare terms weigh more than common ones.
+            assert!(
+                similarity.score(&stats(1, 1), 1, 10) > similarity.score(&stats(50, 200), 1, 10),
+                "{:?}",
+                similarity
+            );
+            assert!(similarity.score(&stats(5, 20), 1, 10) >= 0.0);
+        }
+    }
+
+    #[test]
+    fn test_parameters() {
+        // Parameters are clamped to the ranges where scores follow the contract.
+        let bm25 = Bm25::new(-1.0, 1.5);
+        assert_eq!((bm25.k1(), bm25.b()), (0.0, 1.0));
+        assert_eq!(Bm25::new(1.2, -0.5).b(), 0.0);
+        assert_eq!(Bm25::new(f64::NAN, f64::NAN), Bm25::new(0.0, 0.0));
+        assert_eq!(LmDirichlet::new(-100.0).mu(), f64::EPSILON);
+        assert_eq!(LmJelinekMercer::new(0.0).lambda(), f64::EPSILON);
+        assert_eq!(LmJelinekMercer::new(2.0).lambda(), 1.0);
+        assert_eq!(Dfr::new(-1.0).c(), 0.0);
+
+        let similarities: Vec<Box<dyn Similarity>> = vec![
+            Box::new(Bm25::new(1.2, 2.0)),
+            Box::new(Bm25::new(-1.0, -1.0)),
+            Box::new(LmDirichlet::new(0.0)),
+            Box::new(LmJelinekMercer::new(0.0)),
+            Box::new(LmJelinekMercer::new(2.0)),
+            Box::new(Dfr::new(-1.0)),
+        ];
+        for similarity in similarities {
+            let score = |freq, doc_length| similarity.score(&stats(5, 20), freq, doc_length);
+            assert!(
+                score(1, 10).is_finite() && score(1, 10) >= 0.0,
+                "{:?}",
+                similarity
+            );
+            assert!(score(2, 10) >= score(1, 10), "{:?}", similarity);
+            assert!(score(1, 5) >= score(1, 20), "{:?}", similarity);
+        }
+    }
+}
@@ -0,0 +1,267 @@
+use std::convert::{TryFrom, TryInto};
+use std::path::Path;
+
+use crate::bytes::Bytes;
+use crate::error::IndexError;
+use crate::format::{self, FileKind};
+use crate::schema::Document;
+
+/// Number of documents per compressed block of stored fields.
+pub const STORE_BLOCK_SIZE: usize = 64;
+
+/// Upper bound of the ratio between the decompressed and compressed sizes of an LZ4 block.
+/// A larger decompressed size, read from a corrupted block, is rejected before allocating.
+const MAX_COMPRESSION_RATIO: usize = 255;
+
+// A stored fields file holds, in order:
+//
+// - the compressed blocks of documents, as compressed by `StoreBlock::compress`;
+// - the bincode encoded directory of the blocks: the first doc_id, the number of
+//   documents and the compressed length of each block;
+// - the offset of the directory, as a little-endian u64.
+
+/// The stored field values of the documents of an index, by doc_id. Documents are
+/// serialized and compressed with LZ4 in blocks of `STORE_BLOCK_SIZE`, so that reading a
+/// document only decompresses its block. The documents of the last, incomplete block are
+/// kept uncompressed until the store is encoded. The blocks of a store that was read are
+/// mapped from its file, and only read from disk when their documents are.
+#[derive(Debug, Default)]
+pub struct StoredFields {
+    blocks: Vec<StoreBlock>,
+    pending: Vec<Document>, // documents following the last block
+}
+
+#[derive(Debug)]
+struct StoreBlock {
+    first_doc: usize,
+    len: usize,
+    data: Bytes, // LZ4 compressed bincode of the documents
+}
+
+impl StoreBlock {
+    fn compress(first_doc: usize, documents: &[Document]) -> Self {
+        let encoded = bincode::serialize(documents).unwrap();
+        StoreBlock {
+            first_doc,
+            len: documents.len(),
+            data: lz4_flex::compress_prepend_size(&encoded).into(),
+        }
+    }
+
+    /// The documents of the block, or `None` if the block is corrupted.
+    fn decompress(&self) -> Option<Vec<Document>> {
+        let size = self.data.get(..4)?.try_into().ok()?;
+        let size = usize::try_from(u32::from_le_bytes(size)).ok()?;
+        let compressed = &self.data[4..];
+        if size > compressed.len().saturating_mul(MAX_COMPRESSION_RATIO) {
+            return None;
+        }
+        let encoded = lz4_flex::decompress(compressed, size).ok()?;
+        bincode::deserialize(&encoded).ok()
+    }
+}
+
+impl StoredFields {
+    pub fn new() -> Self {
+        StoredFields::default()
+    }
+
+    /// Number of documents in the store.
+    pub fn len(&self) -> usize {
+        self.next_doc() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn next_doc(&self) -> usize {
+        self.blocks
+            .last()
+            .map_or(1, |block| block.first_doc + block.len)
+            + self.pending.len()
+    }
+
+    /// Appends the stored values of the next document. Documents must be added in doc_id
+    /// order, starting from 1, including those without any stored value.
+    pub fn add(&mut self, document: Document) {
+        self.pending.push(document);
+        if self.pending.len() == STORE_BLOCK_SIZE {
+            let first_doc = self.next_doc() - self.pending.len();
+            self.blocks
+                .push(StoreBlock::compress(first_doc, &self.pending));
+            self.pending.clear();
+        }
+    }
+
+    /// The stored values of a document, or `None` if there is no such document or its
+    /// block is corrupted.
+    pub fn get(&self, doc_id: usize) -> Option<Document> {
+        if doc_id == 0 {
+            return None;
+        }
+        let pending_first_doc = self.next_doc() - self.pending.len();
+        if doc_id >= pending_first_doc {
+            return self.pending.get(doc_id - pending_first_doc).cloned();
+        }
+        let block = &self.blocks[self
+            .blocks
+            .partition_point(|block| block.first_doc + block.len <= doc_id)];
+        block
+            .decompress()?
+            .into_iter()
+            .nth(doc_id - block.first_doc)
+    }
+
+    /// Encodes the store as a stored fields file body, compressing the pending documents
+    /// into a last block.
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let pending = if self.pending.is_empty() {
+            None
+        } else {
+            let first_doc = self.next_doc() - self.pending.len();
+            Some(StoreBlock::compress(first_doc, &self.pending))
+        };
+        let mut data = vec![];
+        let mut directory = vec![];
+        for block in self.blocks.iter().chain(pending.as_ref()) {
+            directory.push((
+                block.first_doc as u64,
+                block.len as u64,
+                block.data.len() as u64,
+            ));
+            data.extend_from_slice(&block.data);
+        }
+        let directory_offset = data.len() as u64;
+        bincode::serialize_into(&mut data, &directory).unwrap();
+        data.extend_from_slice(&directory_offset.to_le_bytes());
+        data
+    }
+}
+
+/// Maps a stored fields file written from `StoredFields::encode`, and returns the mapped
+/// file and the store. Blocks are only read when their documents are. The header of the
+/// file is checked, but not its checksum, which would read all of it: see
+/// `Index::check_integrity`.
+pub(crate) fn read_stored_fields(path: &Path) -> Result<(Bytes, StoredFields), IndexError> {
+    let file = Bytes::map(path).map_err(|source| IndexError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let body = format::check_header(path, FileKind::StoredFields, &file)?;
+    let data = file.slice(body).unwrap();
+    let corrupted = |message: &str| IndexError::corrupted(path.to_path_buf(), message);
+    let end = data
+        .len()
+        .checked_sub(8)
+        .ok_or_else(|| corrupted("file too short"))?;
+    let directory_offset = data[end..]
+        .try_into()
+        .ok()
+        .and_then(|offset| usize::try_from(u64::from_le_bytes(offset)).ok())
+        .filter(|&offset| offset <= end)
+        .ok_or_else(|| corrupted("invalid directory offset"))?;
+    let directory: Vec<(u64, u64, u64)> = bincode::deserialize(&data[directory_offset..end])
+        .map_err(|err| corrupted(&err.to_string()))?;
+
+    let mut blocks = Vec::with_capacity(directory.len());
+    let (mut next_doc, mut offset) = (1, 0usize);
+    for (first_doc, len, data_len) in directory {
+        let (first_doc, len, data_len) = (first_doc as usize, len as usize, data_len as usize);
+        let block_data = offset
+            .checked_add(data_len)
+            .filter(|&block_end| first_doc == next_doc && len > 0 && block_end <= directory_offset)
+            .and_then(|block_end| data.slice(offset..block_end))
+            .ok_or_else(|| corrupted(&format!("invalid block of doc_id {}", first_doc)))?;
+        blocks.push(StoreBlock {
+            first_doc,
+            len,
+            data: block_data,
+        });
+        next_doc = first_doc
+            .checked_add(len)
+            .ok_or_else(|| corrupted("too many documents"))?;
+        offset += data_len;
+    }
+    let store = StoredFields {
+        blocks,
+        pending: vec![],
+    };
+    Ok((file, store))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_stored_fields, StoredFields, STORE_BLOCK_SIZE};
+    use crate::bytes::Bytes;
+    use crate::error::IndexError;
+    use crate::format::FileKind;
+    use crate::index::write_bytes;
+    use crate::schema::Document;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn test_stored_fields() {
+        let mut store = StoredFields::new();
+        let count = STORE_BLOCK_SIZE * 2 + 11;
+        for i in 1..=count {
+            let mut document = Document::new();
+            if i % 3 != 0 {
+                document.add_value("id", &format!("doc-{}", i));
+            }
+            store.add(document);
+        }
+        assert_eq!(store.len(), count);
+
+        let check = |store: &StoredFields| {
+            assert_eq!(store.get(0), None);
+            assert_eq!(store.get(1).unwrap().get_first("id"), Some("doc-1"));
+            assert_eq!(store.get(3).unwrap(), Document::new());
+            assert_eq!(
+                store.get(STORE_BLOCK_SIZE + 1).unwrap().get_first("id"),
+                Some(format!("doc-{}", STORE_BLOCK_SIZE + 1).as_str())
+            );
+            assert_eq!(
+                store.get(count).unwrap().get_first("id"),
+                Some(format!("doc-{}", count).as_str())
+            );
+            assert_eq!(store.get(count + 1), None);
+        };
+        check(&store);
+
+        // The pending documents are compressed when the store is written, and the blocks
+        // of a store that was read are mapped from its file.
+        let directory = TempDir::new("store");
+        let path = directory.join("_0.store");
+        write_bytes(&path, FileKind::StoredFields, &store.encode()).unwrap();
+        let (_, read) = read_stored_fields(&path).unwrap();
+        write_bytes(&path, FileKind::StoredFields, b"short").unwrap();
+        let short = read_stored_fields(&path);
+
+        assert_eq!(read.blocks.len(), 3);
+        assert!(read.pending.is_empty());
+        assert!(matches!(read.blocks[0].data, Bytes::Mapped { .. }));
+        check(&read);
+        assert!(matches!(short, Err(IndexError::Corrupted { .. })));
+    }
+
+    #[test]
+    fn test_corrupted_size() {
+        let mut store = StoredFields::new();
+        for i in 1..=STORE_BLOCK_SIZE * 2 {
+            store.add(Document::new().with_value("id", &format!("doc-{}", i)));
+        }
+        // The first block starts with its decompressed size, as a little-endian u32.
+        let mut encoded = store.encode();
+        encoded[3] = 0x7f;
+        let directory = TempDir::new("store-size");
+        let path = directory.join("_0.store");
+        write_bytes(&path, FileKind::StoredFields, &encoded).unwrap();
+        let (_, read) = read_stored_fields(&path).unwrap();
+        assert_eq!(read.get(1), None);
+        assert_eq!(
+            read.get(STORE_BLOCK_SIZE + 1).unwrap().get_first("id"),
+            Some(format!("doc-{}", STORE_BLOCK_SIZE + 1).as_str())
+        );
+    }
+}
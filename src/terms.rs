@@ -0,0 +1,388 @@
+use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
+use std::io;
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+
+use fst::map::Stream;
+use fst::{IntoStreamer, Map, MapBuilder, Streamer};
+
+use crate::bytes::Bytes;
+use crate::error::IndexError;
+use crate::format::{self, FileKind};
+use crate::index::{FieldIndex, PostingsList};
+
+pub mod automaton;
+
+use self::automaton::AlwaysMatch;
+pub use self::automaton::Automaton;
+
+// A postings file holds, in order:
+//
+// - the postings lists of all the terms, as encoded by `PostingsList::encode`;
+// - the term dictionary of each field, a finite state transducer mapping its terms to the
+//   offsets of their postings lists;
+// - the bincode encoded directory of the fields: the name of each field and the offset
+//   and length of its term dictionary;
+// - the offset of the directory, as a little-endian u64.
+
+/// Encodes the term dictionaries and postings lists of the fields of a segment.
+pub(crate) fn encode_postings(fields: &HashMap<String, FieldIndex>) -> io::Result<Vec<u8>> {
+    let mut data = vec![];
+    let mut directory = vec![];
+    let mut names = fields.keys().collect::<Vec<&String>>();
+    names.sort();
+    for name in names {
+        let field = &fields[name];
+        let mut builder = MapBuilder::memory();
+        for term in field.terms() {
+            let term = term?;
+            if let Some(postings_list) = field.get_postings_list(&term) {
+                builder.insert(&term, data.len() as u64).unwrap();
+                postings_list.encode(&mut data);
+            }
+        }
+        let dictionary = builder.into_inner().unwrap();
+        directory.push((name.clone(), data.len() as u64, dictionary.len() as u64));
+        data.extend_from_slice(&dictionary);
+    }
+    let directory_offset = data.len() as u64;
+    bincode::serialize_into(&mut data, &directory).unwrap();
+    data.extend_from_slice(&directory_offset.to_le_bytes());
+    Ok(data)
+}
+
+/// Maps a postings file written from `encode_postings`, and returns the mapped file and
+/// the term dictionary of each field. Postings lists are only read when they are looked
+/// up. The header of the file is checked, but not its checksum, which would read all of
+/// it: see `Index::check_integrity`.
+pub(crate) fn read_postings(
+    path: &Path,
+) -> Result<(Bytes, HashMap<String, TermDictionary>), IndexError> {
+    let file = Bytes::map(path).map_err(|source| IndexError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let body = format::check_header(path, FileKind::Postings, &file)?;
+    let data = file.slice(body).unwrap();
+    let corrupted = |message: &str| IndexError::corrupted(path.to_path_buf(), message);
+    let end = data
+        .len()
+        .checked_sub(8)
+        .ok_or_else(|| corrupted("file too short"))?;
+    let directory_offset = data[end..]
+        .try_into()
+        .ok()
+        .and_then(|offset| usize::try_from(u64::from_le_bytes(offset)).ok())
+        .filter(|&offset| offset <= end)
+        .ok_or_else(|| corrupted("invalid directory offset"))?;
+    let directory: Vec<(String, u64, u64)> = bincode::deserialize(&data[directory_offset..end])
+        .map_err(|err| corrupted(&err.to_string()))?;
+
+    let mut dictionaries = HashMap::new();
+    for (name, start, len) in directory {
+        let invalid = || corrupted(&format!("invalid term dictionary of {}", name));
+        let (start, len) = (start as usize, len as usize);
+        let bytes = start
+            .checked_add(len)
+            .filter(|&dictionary_end| dictionary_end <= directory_offset)
+            .and_then(|dictionary_end| data.slice(start..dictionary_end))
+            .ok_or_else(invalid)?;
+        let terms = Map::new(bytes).map_err(|_| invalid())?;
+        let postings = data.slice(0..start).ok_or_else(invalid)?;
+        let path = path.to_path_buf();
+        dictionaries.insert(
+            name,
+            TermDictionary {
+                terms,
+                postings,
+                path,
+            },
+        );
+    }
+    Ok((file, dictionaries))
+}
+
+/// The terms of a field in a memory-mapped postings file, stored as a finite state
+/// transducer mapping each term to the offset of its postings list. Terms share their
+/// prefixes and suffixes, are iterated in order, and can be searched with an automaton,
+/// such as a prefix, a wildcard, a regular expression or a Levenshtein automaton.
+#[derive(Clone)]
+pub struct TermDictionary {
+    terms: Map<Bytes>,
+    postings: Bytes, // postings lists of the field and those before it
+    path: PathBuf,   // path of the postings file, to report corrupted terms
+}
+
+impl TermDictionary {
+    pub fn len(&self) -> usize {
+        self.terms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// The postings list of `term`, mapped from the postings file without decoding its
+    /// postings. Returns `None` if there is no such term, or if the header of its
+    /// postings list is inconsistent: see `Index::check_integrity`.
+    pub fn get(&self, term: &str) -> Option<PostingsList> {
+        let offset = usize::try_from(self.terms.get(term)?).ok()?;
+        PostingsList::decode(&self.postings.slice(offset..self.postings.len())?)
+    }
+
+    /// The terms in increasing order.
+    pub fn terms(&self) -> TermIter<'_, AlwaysMatch> {
+        self.search(AlwaysMatch)
+    }
+
+    /// The terms accepted by `automaton`, in increasing order.
+    pub fn search<A: Automaton>(&self, automaton: A) -> TermIter<'_, A> {
+        TermIter {
+            stream: self.terms.search(FstAutomaton(automaton)).into_stream(),
+            path: &self.path,
+        }
+    }
+
+    /// The terms between `start` and `end`, in increasing order.
+    pub fn range(&self, start: Bound<&str>, end: Bound<&str>) -> TermIter<'_, AlwaysMatch> {
+        let builder = self.terms.search(FstAutomaton(AlwaysMatch));
+        let builder = match start {
+            Bound::Included(start) => builder.ge(start),
+            Bound::Excluded(start) => builder.gt(start),
+            Bound::Unbounded => builder,
+        };
+        let builder = match end {
+            Bound::Included(end) => builder.le(end),
+            Bound::Excluded(end) => builder.lt(end),
+            Bound::Unbounded => builder,
+        };
+        TermIter {
+            stream: builder.into_stream(),
+            path: &self.path,
+        }
+    }
+}
+
+impl fmt::Debug for TermDictionary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TermDictionary")
+            .field("len", &self.len())
+            .field("postings", &self.postings)
+            .finish()
+    }
+}
+
+/// An iterator over terms of a `TermDictionary`, in increasing order. A term that is not
+/// valid UTF-8 is reported as a corrupted postings file.
+pub struct TermIter<'a, A: Automaton> {
+    stream: Stream<'a, FstAutomaton<A>>,
+    path: &'a Path,
+}
+
+impl<A: Automaton> Iterator for TermIter<'_, A> {
+    type Item = Result<String, IndexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (term, _) = self.stream.next()?;
+        Some(
+            std::str::from_utf8(term)
+                .map(str::to_string)
+                .map_err(|_| IndexError::corrupted(self.path.to_path_buf(), "invalid term")),
+        )
+    }
+}
+
+/// Runs an `Automaton` on a term dictionary.
+struct FstAutomaton<A>(A);
+
+impl<A: Automaton> fst::Automaton for FstAutomaton<A> {
+    type State = A::State;
+
+    fn start(&self) -> A::State {
+        self.0.start()
+    }
+
+    fn is_match(&self, state: &A::State) -> bool {
+        self.0.is_match(state)
+    }
+
+    fn can_match(&self, state: &A::State) -> bool {
+        self.0.can_match(state)
+    }
+
+    fn will_always_match(&self, state: &A::State) -> bool {
+        self.0.will_always_match(state)
+    }
+
+    fn accept(&self, state: &A::State, byte: u8) -> A::State {
+        self.0.accept(state, byte)
+    }
+}
+
+/// Whether `automaton` accepts `term`, for the terms that are not in a `TermDictionary`.
+pub(crate) fn is_match<A: Automaton>(automaton: &A, term: &str) -> bool {
+    let mut state = automaton.start();
+    for &byte in term.as_bytes() {
+        if !automaton.can_match(&state) {
+            return false;
+        }
+        state = automaton.accept(&state, byte);
+    }
+    automaton.is_match(&state)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Bound;
+
+    use fst::MapBuilder;
+
+    use super::automaton::{AlwaysMatch, Automaton, Str, Subsequence};
+    use super::{encode_postings, is_match, read_postings};
+    use crate::error::IndexError;
+    use crate::format::FileKind;
+    use crate::index::{write_bytes, Index, PostingsList};
+    use crate::schema::{Document, FieldEntry, Schema};
+    use crate::testing::TempDir;
+
+    fn collect(terms: impl Iterator<Item = Result<String, IndexError>>) -> Vec<String> {
+        terms.collect::<Result<_, _>>().unwrap()
+    }
+
+    #[test]
+    fn test_postings_file() {
+        let schema = Schema::new()
+            .with_field(FieldEntry::new("title"))
+            .with_field(FieldEntry::new("body"));
+        let mut index = Index::with_schema(schema);
+        for i in 0..300 {
+            let document = Document::new()
+                .with_value("title", &format!("dog {}", i % 7))
+                .with_value("body", if i % 2 == 0 { "even" } else { "odd odd" });
+            index.add_document(&document).unwrap();
+        }
+        let directory = TempDir::new("postings");
+        let path = directory.join("_0.post");
+        write_bytes(
+            &path,
+            FileKind::Postings,
+            &encode_postings(index.fields()).unwrap(),
+        )
+        .unwrap();
+        let (_, dictionaries) = read_postings(&path).unwrap();
+        let short_path = directory.join("_1.post");
+        std::fs::write(&short_path, b"short").unwrap();
+        let short = read_postings(&short_path);
+
+        assert_eq!(dictionaries.len(), 2);
+        let title = &dictionaries["title"];
+        assert_eq!(title.len(), 8);
+        assert_eq!(
+            collect(title.terms()),
+            vec!["0", "1", "2", "3", "4", "5", "6", "dog"]
+        );
+        for term in collect(title.terms()) {
+            let expected = index.field("title").unwrap().get_postings_list(&term);
+            assert_eq!(
+                title.get(&term).unwrap().doc_ids().unwrap(),
+                expected.unwrap().doc_ids().unwrap()
+            );
+        }
+        assert_eq!(title.get("dog").unwrap().len(), 300);
+        assert!(title.get("cat").is_none());
+        assert!(title.get("").is_none());
+        assert!(title.get("zebra").is_none());
+
+        let odd = dictionaries["body"].get("odd").unwrap();
+        assert_eq!(odd.len(), 150);
+        assert_eq!(odd.collection_freq(), 300);
+        assert_eq!(odd.doc_ids().unwrap()[..3], [2, 4, 6]);
+
+        assert!(matches!(short, Err(IndexError::Corrupted { .. })));
+    }
+
+    #[test]
+    fn test_invalid_term() {
+        // A postings file like those of `encode_postings`, with a term that is not UTF-8.
+        let mut data = vec![];
+        let mut postings_list = PostingsList::new();
+        postings_list.add(1, vec![0], 1);
+        postings_list.encode(&mut data);
+        let mut builder = MapBuilder::memory();
+        builder.insert(b"a", 0).unwrap();
+        builder.insert(b"\xff", 0).unwrap();
+        let dictionary = builder.into_inner().unwrap();
+        let directory = vec![(String::new(), data.len() as u64, dictionary.len() as u64)];
+        data.extend_from_slice(&dictionary);
+        let directory_offset = data.len() as u64;
+        bincode::serialize_into(&mut data, &directory).unwrap();
+        data.extend_from_slice(&directory_offset.to_le_bytes());
+        let directory = TempDir::new("invalid-term");
+        let path = directory.join("_0.post");
+        write_bytes(&path, FileKind::Postings, &data).unwrap();
+        let (_, dictionaries) = read_postings(&path).unwrap();
+
+        let terms = dictionaries[""].terms().collect::<Vec<_>>();
+        assert_eq!(terms.len(), 2);
+        assert_eq!(terms[0].as_ref().unwrap(), "a");
+        assert!(matches!(terms[1], Err(IndexError::Corrupted { .. })));
+        assert!(dictionaries[""]
+            .search(AlwaysMatch)
+            .nth(1)
+            .unwrap()
+            .is_err());
+    }
+
+    #[test]
+    fn test_search_terms() {
+        let mut index = Index::new();
+        index
+            .add("sheltie sheepdog shepherd corgi collie cardigan")
+            .unwrap();
+        let directory = TempDir::new("search");
+        let path = directory.join("_0.post");
+        write_bytes(
+            &path,
+            FileKind::Postings,
+            &encode_postings(index.fields()).unwrap(),
+        )
+        .unwrap();
+        let (_, dictionaries) = read_postings(&path).unwrap();
+        let dictionary = dictionaries.values().next().unwrap();
+
+        let prefix = Str::new("she").starts_with();
+        assert_eq!(
+            collect(dictionary.search(&prefix)),
+            vec!["sheepdog", "sheltie", "shepherd"]
+        );
+        assert_eq!(
+            collect(dictionary.search(Subsequence::new("cli"))),
+            vec!["collie"]
+        );
+        assert_eq!(
+            collect(dictionary.search(Str::new("corgi").complement())),
+            vec!["cardigan", "collie", "sheepdog", "sheltie", "shepherd"]
+        );
+        assert_eq!(
+            collect(dictionary.range(Bound::Included("collie"), Bound::Excluded("sheltie"))),
+            vec!["collie", "corgi", "sheepdog"]
+        );
+        assert_eq!(
+            collect(dictionary.range(Bound::Excluded("corgi"), Bound::Unbounded)),
+            vec!["sheepdog", "sheltie", "shepherd"]
+        );
+
+        for term in collect(dictionary.terms()) {
+            assert_eq!(
+                is_match(&prefix, &term),
+                collect(dictionary.search(&prefix)).contains(&term)
+            );
+        }
+        assert!(!is_match(&Str::new("corgi"), "corgis"));
+        assert!(is_match(&Str::new(""), ""));
+        assert!(is_match(&Str::new("").starts_with(), "corgi"));
+        assert!(!is_match(&Subsequence::new("").complement(), ""));
+    }
+}
@@ -0,0 +1,226 @@
+/// A finite automaton over the bytes of terms, used to search a `TermDictionary` without
+/// visiting the terms whose prefixes it can no longer accept.
+pub trait Automaton {
+    type State;
+
+    /// The state before any byte is read.
+    fn start(&self) -> Self::State;
+
+    /// Whether the bytes read so far are accepted.
+    fn is_match(&self, state: &Self::State) -> bool;
+
+    /// Whether some continuation of the bytes read so far may be accepted. Returning
+    /// `true` is always correct, but returning `false` skips the terms with that prefix.
+    fn can_match(&self, _state: &Self::State) -> bool {
+        true
+    }
+
+    /// Whether every continuation of the bytes read so far is accepted.
+    fn will_always_match(&self, _state: &Self::State) -> bool {
+        false
+    }
+
+    /// The state after reading `byte`.
+    fn accept(&self, state: &Self::State, byte: u8) -> Self::State;
+
+    /// Accepts the terms that start with a term this automaton accepts.
+    fn starts_with(self) -> StartsWith<Self>
+    where
+        Self: Sized,
+    {
+        StartsWith(self)
+    }
+
+    /// Accepts the terms this automaton does not accept.
+    fn complement(self) -> Complement<Self>
+    where
+        Self: Sized,
+    {
+        Complement(self)
+    }
+}
+
+impl<A: Automaton> Automaton for &A {
+    type State = A::State;
+
+    fn start(&self) -> Self::State {
+        (*self).start()
+    }
+
+    fn is_match(&self, state: &Self::State) -> bool {
+        (*self).is_match(state)
+    }
+
+    fn can_match(&self, state: &Self::State) -> bool {
+        (*self).can_match(state)
+    }
+
+    fn will_always_match(&self, state: &Self::State) -> bool {
+        (*self).will_always_match(state)
+    }
+
+    fn accept(&self, state: &Self::State, byte: u8) -> Self::State {
+        (*self).accept(state, byte)
+    }
+}
+
+/// Accepts every term.
+#[derive(Clone, Copy, Debug)]
+pub struct AlwaysMatch;
+
+impl Automaton for AlwaysMatch {
+    type State = ();
+
+    fn start(&self) {}
+
+    fn is_match(&self, _state: &()) -> bool {
+        true
+    }
+
+    fn will_always_match(&self, _state: &()) -> bool {
+        true
+    }
+
+    fn accept(&self, _state: &(), _byte: u8) {}
+}
+
+/// Accepts exactly one term.
+#[derive(Clone, Debug)]
+pub struct Str<'a> {
+    term: &'a [u8],
+}
+
+impl<'a> Str<'a> {
+    pub fn new(term: &'a str) -> Self {
+        Str {
+            term: term.as_bytes(),
+        }
+    }
+}
+
+impl Automaton for Str<'_> {
+    type State = Option<usize>; // number of bytes of the term read, None after a mismatch
+
+    fn start(&self) -> Option<usize> {
+        Some(0)
+    }
+
+    fn is_match(&self, state: &Option<usize>) -> bool {
+        *state == Some(self.term.len())
+    }
+
+    fn can_match(&self, state: &Option<usize>) -> bool {
+        state.is_some()
+    }
+
+    fn accept(&self, state: &Option<usize>, byte: u8) -> Option<usize> {
+        state
+            .filter(|&i| self.term.get(i) == Some(&byte))
+            .map(|i| i + 1)
+    }
+}
+
+/// Accepts the terms that contain the bytes of a string in order, but not necessarily
+/// next to each other.
+#[derive(Clone, Debug)]
+pub struct Subsequence<'a> {
+    subsequence: &'a [u8],
+}
+
+impl<'a> Subsequence<'a> {
+    pub fn new(subsequence: &'a str) -> Self {
+        Subsequence {
+            subsequence: subsequence.as_bytes(),
+        }
+    }
+}
+
+impl Automaton for Subsequence<'_> {
+    type State = usize; // number of bytes of the subsequence found
+
+    fn start(&self) -> usize {
+        0
+    }
+
+    fn is_match(&self, &state: &usize) -> bool {
+        state == self.subsequence.len()
+    }
+
+    fn will_always_match(&self, &state: &usize) -> bool {
+        state == self.subsequence.len()
+    }
+
+    fn accept(&self, &state: &usize, byte: u8) -> usize {
+        if self.subsequence.get(state) == Some(&byte) {
+            state + 1
+        } else {
+            state
+        }
+    }
+}
+
+/// See `Automaton::starts_with`.
+#[derive(Clone, Debug)]
+pub struct StartsWith<A>(A);
+
+impl<A: Automaton> Automaton for StartsWith<A> {
+    type State = Option<A::State>; // None once a prefix was accepted
+
+    fn start(&self) -> Self::State {
+        let state = self.0.start();
+        if self.0.is_match(&state) {
+            None
+        } else {
+            Some(state)
+        }
+    }
+
+    fn is_match(&self, state: &Self::State) -> bool {
+        state.is_none()
+    }
+
+    fn can_match(&self, state: &Self::State) -> bool {
+        state.as_ref().is_none_or(|state| self.0.can_match(state))
+    }
+
+    fn will_always_match(&self, state: &Self::State) -> bool {
+        state.is_none()
+    }
+
+    fn accept(&self, state: &Self::State, byte: u8) -> Self::State {
+        let state = self.0.accept(state.as_ref()?, byte);
+        if self.0.is_match(&state) {
+            None
+        } else {
+            Some(state)
+        }
+    }
+}
+
+/// See `Automaton::complement`.
+#[derive(Clone, Debug)]
+pub struct Complement<A>(A);
+
+impl<A: Automaton> Automaton for Complement<A> {
+    type State = A::State;
+
+    fn start(&self) -> Self::State {
+        self.0.start()
+    }
+
+    fn is_match(&self, state: &Self::State) -> bool {
+        !self.0.is_match(state)
+    }
+
+    fn can_match(&self, state: &Self::State) -> bool {
+        !self.0.will_always_match(state)
+    }
+
+    fn will_always_match(&self, state: &Self::State) -> bool {
+        !self.0.can_match(state)
+    }
+
+    fn accept(&self, state: &Self::State, byte: u8) -> Self::State {
+        self.0.accept(state, byte)
+    }
+}
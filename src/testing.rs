@@ -0,0 +1,36 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A directory for the files of a test, removed with its contents when dropped, including
+/// when an assertion fails first.
+#[derive(Debug)]
+pub(crate) struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Creates an empty directory named after `name`, which must be unique among the tests,
+    /// as they run in parallel.
+    pub fn new(name: &str) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("sheltie-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The path of a file in the directory.
+    pub fn join(&self, file: &str) -> PathBuf {
+        self.path.join(file)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}